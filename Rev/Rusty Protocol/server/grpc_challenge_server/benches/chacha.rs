@@ -0,0 +1,54 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+// The cipher modules compiled straight from the server tree, the way
+// build.rs pulls in the codec (the server itself is a binary-only crate).
+#[allow(dead_code)]
+#[path = "../src/server/verify"]
+mod verify {
+        pub mod error;
+        pub mod stage_cipher;
+        pub mod chacha_logic;
+}
+
+use verify::chacha_logic::{chacha20_block, encrypt, ChaChaCipher, ChaChaVariant, INITIAL_COUNTER};
+
+const KEY: [u8; 32] = *b"j_k0o'g_oNnk_Gr_k3hAvUg_7w!D_0o3";
+const NONCE: [u8; 12] = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb];
+
+// `encrypt` as it was before ChaChaCipher: every block rebuilds the state
+// from the key and returns a fresh Vec, the keystream is collected into a
+// second Vec and XORed into a third.
+fn encrypt_per_block(key: &[u8], nonce: &[u8], counter: u32, plaintext: &[u8]) -> Vec<u8> {
+        let mut ks = Vec::with_capacity(plaintext.len());
+        let mut ctr = counter;
+        while ks.len() < plaintext.len() {
+                let block = chacha20_block(ChaChaVariant::Custom, key, ctr, nonce).unwrap();
+                let take = (plaintext.len() - ks.len()).min(64);
+                ks.extend_from_slice(&block[..take]);
+                ctr = ctr.wrapping_add(1);
+        }
+        plaintext.iter().zip(&ks).map(|(p, k)| p ^ k).collect()
+}
+
+fn bench_chacha(c: &mut Criterion) {
+        let mut group = c.benchmark_group("chacha_custom");
+        for len in [1usize << 10, 64 << 10, 1 << 20] {
+                let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+                assert_eq!(encrypt_per_block(&KEY, &NONCE, INITIAL_COUNTER, &data), encrypt(&KEY, &NONCE, INITIAL_COUNTER, &data).unwrap());
+                group.throughput(Throughput::Bytes(len as u64));
+                group.bench_with_input(BenchmarkId::new("per_block_encrypt", len), &data, |b, data| {
+                        b.iter(|| encrypt_per_block(&KEY, &NONCE, INITIAL_COUNTER, data))
+                });
+                group.bench_with_input(BenchmarkId::new("encrypt", len), &data, |b, data| {
+                        b.iter(|| encrypt(&KEY, &NONCE, INITIAL_COUNTER, data).unwrap())
+                });
+                let mut buf = data.clone();
+                group.bench_with_input(BenchmarkId::new("apply_keystream", len), &len, |b, _| {
+                        b.iter(|| ChaChaCipher::new(ChaChaVariant::Custom, &KEY, &NONCE, INITIAL_COUNTER).apply_keystream(&mut buf))
+                });
+        }
+        group.finish();
+}
+
+criterion_group!(benches, bench_chacha);
+criterion_main!(benches);
@@ -0,0 +1,89 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+// The cipher modules compiled straight from the server tree, the way
+// build.rs pulls in the codec (the server itself is a binary-only crate).
+#[allow(dead_code)]
+#[path = "../src/server/verify"]
+mod verify {
+        pub mod error;
+        pub mod stage_cipher;
+        pub mod block_ctr;
+        pub mod cham_logic;
+}
+
+use verify::cham_logic::{cham_key_schedule_128_256, encrypt, to_u32_le_words, ChamCtr, ChamKey, ChamVariant, INITIAL_COUNTER};
+
+const KEY: &[u8; 32] = b"i_7H1nk_U'v3_DoNe_QUiTe_@_LO7_:)";
+const NONCE: [u8; 12] = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b];
+
+// `encrypt` as it was before ChamKey: every counter block re-runs the key
+// schedule and splits the block into a fresh word Vec, and every output
+// chunk is collected into its own Vec.
+fn encrypt_per_block(key32: &[u8], nonce12: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let rounds = ChamVariant::Challenge.rounds();
+        let mut out = Vec::new();
+        for (i, chunk) in plaintext.chunks(16).enumerate() {
+                let mut cb = [0u8; 16];
+                cb[..12].copy_from_slice(nonce12);
+                cb[12..].copy_from_slice(&(i as u32 + 1).to_be_bytes());
+                let (rk_even, rk_odd) = cham_key_schedule_128_256(key32).unwrap();
+                let w = to_u32_le_words(&cb).unwrap();
+                let (mut s0, mut s1, mut s2, mut s3) = (w[0], w[1], w[2], w[3]);
+                for r in 0..rounds {
+                        let new_s = if (r & 1) == 0 {
+                                (s0 ^ r as u32).wrapping_add(s1.rotate_left(1) ^ rk_even[r % 8]).rotate_left(8)
+                        } else {
+                                (s0 ^ r as u32).wrapping_add(s1.rotate_left(8) ^ rk_odd[r % 8]).rotate_left(1)
+                        };
+                        s0 = s1; s1 = s2; s2 = s3; s3 = new_s;
+                }
+                let ks: Vec<u8> = [s3, s2, s1, s0].iter().flat_map(|w| w.to_le_bytes()).collect();
+                out.extend(chunk.iter().zip(&ks).map(|(p, k)| p ^ k).collect::<Vec<u8>>());
+        }
+        out
+}
+
+// ChamKey with its schedule expanded once, but one block at a time.
+fn encrypt_single_lane(key: &ChamKey, nonce12: &[u8], data: &mut [u8]) {
+        let rounds = ChamVariant::Challenge.rounds();
+        for (i, chunk) in data.chunks_mut(16).enumerate() {
+                let mut cb = [0u8; 16];
+                cb[..12].copy_from_slice(nonce12);
+                cb[12..].copy_from_slice(&(i as u32 + 1).to_be_bytes());
+                let ks = key.encrypt_block(&cb, rounds);
+                chunk.iter_mut().zip(&ks).for_each(|(d, k)| *d ^= k);
+        }
+}
+
+fn bench_cham_ctr(c: &mut Criterion) {
+        let mut group = c.benchmark_group("cham_ctr");
+        group.sample_size(10);
+        let key = ChamKey::new(KEY).unwrap();
+        for mib in [1usize, 4] {
+                let data: Vec<u8> = (0..mib << 20).map(|i| i as u8).collect();
+                let expected = encrypt(KEY, &NONCE, &data).unwrap();
+                assert_eq!(encrypt_per_block(KEY, &NONCE, &data), expected);
+                let mut single = data.clone();
+                encrypt_single_lane(&key, &NONCE, &mut single);
+                assert_eq!(single, expected);
+
+                group.throughput(Throughput::Bytes(data.len() as u64));
+                group.bench_with_input(BenchmarkId::new("per_block_encrypt", mib << 20), &data, |b, data| {
+                        b.iter(|| encrypt_per_block(KEY, &NONCE, data))
+                });
+                let mut buf = data.clone();
+                group.bench_with_input(BenchmarkId::new("cham_key_single_lane", mib << 20), &mib, |b, _| {
+                        b.iter(|| encrypt_single_lane(&key, &NONCE, &mut buf))
+                });
+                group.bench_with_input(BenchmarkId::new("cham_ctr", mib << 20), &mib, |b, _| {
+                        b.iter(|| {
+                                let mut ctr = ChamCtr::new(key.clone(), &NONCE, INITIAL_COUNTER).unwrap();
+                                ctr.apply_keystream(&mut buf).unwrap()
+                        })
+                });
+        }
+        group.finish();
+}
+
+criterion_group!(benches, bench_cham_ctr);
+criterion_main!(benches);
@@ -0,0 +1,77 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use num_bigint::BigUint;
+use num_traits::Zero;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+// The codec and the event constants, compiled in the same way build.rs
+// does it (the server itself is a binary-only crate).
+#[allow(dead_code)]
+#[path = "../src/server"]
+mod server {
+        pub mod secret_provider;
+        pub mod mixed_radix;
+}
+
+use server::mixed_radix::{MixedRadixCodec, DEFAULT_CODEC};
+
+// The encoder as it was before tables were cached: one full bignum division
+// per digit, and every output character re-derives its position's base and
+// reshuffles the alphabet.
+fn encode_per_digit(codec: &MixedRadixCodec, data: &[u8]) -> String {
+        let mut num = BigUint::from_bytes_be(data);
+        let mut digits = Vec::new();
+        let mut pos = 0u32;
+        while !num.is_zero() {
+                let base = codec.pos_base(pos);
+                digits.push((&num % base).try_into().unwrap_or(0u32));
+                num /= base;
+                pos += 1;
+        }
+        (0..digits.len())
+                .rev()
+                .map(|pos| {
+                        let alphabet = codec.pos_alphabet(pos as u32, codec.pos_base(pos as u32));
+                        alphabet[digits[pos] as usize]
+                })
+                .collect()
+}
+
+fn input(len: usize) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        StdRng::seed_from_u64(34).fill(&mut data[..]);
+        data[0] |= 1;
+        data
+}
+
+fn bench_encode(c: &mut Criterion) {
+        let mut group = c.benchmark_group("mixed_radix_encode");
+        group.sample_size(10);
+        for kib in [1usize, 4, 8] {
+                let data = input(kib * 1024);
+                assert_eq!(DEFAULT_CODEC.encode(&data), encode_per_digit(&DEFAULT_CODEC, &data));
+                group.throughput(Throughput::Bytes(data.len() as u64));
+                group.bench_with_input(BenchmarkId::new("per_digit", kib * 1024), &data, |b, data| {
+                        b.iter(|| encode_per_digit(&DEFAULT_CODEC, data))
+                });
+                group.bench_with_input(BenchmarkId::new("divide_and_conquer", kib * 1024), &data, |b, data| {
+                        b.iter(|| DEFAULT_CODEC.encode(data))
+                });
+        }
+        group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+        let mut group = c.benchmark_group("mixed_radix_decode");
+        group.sample_size(10);
+        for kib in [1usize, 4, 8] {
+                let encoded = DEFAULT_CODEC.encode(&input(kib * 1024));
+                group.throughput(Throughput::Bytes(kib as u64 * 1024));
+                group.bench_with_input(BenchmarkId::from_parameter(kib * 1024), &encoded, |b, encoded| {
+                        b.iter(|| DEFAULT_CODEC.decode(encoded).unwrap())
+                });
+        }
+        group.finish();
+}
+
+criterion_group!(benches, bench_encode, bench_decode);
+criterion_main!(benches);
@@ -1,3 +1,22 @@
-fn main() {
-        tonic_build::compile_protos("proto/secret.proto").unwrap();
-}
\ No newline at end of file
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// The codec and event constants are compiled into the build script as well,
+// so TARGET_CIPHER always matches the credentials in secret_provider.rs.
+#[allow(dead_code)]
+#[path = "src/server"]
+mod server {
+        pub mod secret_provider;
+        pub mod mixed_radix;
+}
+
+fn main() {
+        tonic_build::compile_protos("proto/secret.proto").unwrap();
+
+        println!("cargo:rerun-if-changed=src/server/secret_provider.rs");
+        println!("cargo:rerun-if-changed=src/server/mixed_radix.rs");
+        let target = server::mixed_radix::DEFAULT_CODEC.encode(server::secret_provider::stage1_answer().as_bytes());
+        let out = Path::new(&env::var("OUT_DIR").unwrap()).join("target_cipher.rs");
+        fs::write(out, format!("pub const TARGET_CIPHER: &str = {:?};\n", target)).unwrap();
+}
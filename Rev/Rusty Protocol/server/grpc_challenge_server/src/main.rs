@@ -25,12 +25,14 @@ service PanicVerifier {
                             }
 
 mod server;
-use server::{verify, session}; 
+use server::{verify, session, scoreboard, pow, roster, proxy_proto, acl, mixed_radix, selftest}; 
 
 use tonic::transport::Server;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::time::{timeout, Duration};
+use std::net::SocketAddr;
+use tokio::signal::unix::{signal, SignalKind};
 #[cfg(feature = "server")]
 use self::secret::panic_verifier_server::PanicVerifierServer;
 #[cfg(feature = "server")]
@@ -38,20 +40,35 @@ pub mod secret {
         tonic::include_proto!("hidden");
 }
 
-#[no_mangle] pub static KEY_BYTES: &[u8] = b"ringover";
-#[no_mangle] pub static ALLOWED_BASES: &[u32] = &[57, 58, 59, 61, 62];
-#[no_mangle] pub static SUPER_ALPHABET: &str = "f8Z1qUshg6V2onE47Xk5RdpQeKyH9CGbWmLxMvaAjSNJTwzPBYrDc3tuFi0OIl";
-#[no_mangle] pub static TARGET_CIPHER: &str = "R6GIDK4azALROzsFmFhj1fRTE4VJlKeCP83UpUW1icPCDcWgzp0cxR6t0NVWXJWL";
+#[no_mangle] pub static KEY_BYTES: &[u8] = server::secret_provider::KEY_BYTES;
+#[no_mangle] pub static ALLOWED_BASES: &[u32] = server::secret_provider::ALLOWED_BASES;
+#[no_mangle] pub static SUPER_ALPHABET: &str = server::secret_provider::SUPER_ALPHABET;
+#[no_mangle] pub static TARGET_CIPHER: &str = verify::TARGET_CIPHER;
 
-async fn handle_connection(stream: TcpStream) {
-        if let Ok(peer) = stream.peer_addr() {
-                    println!("[+] New connection from {}", peer);
-                        }
+async fn handle_connection(stream: TcpStream, peer: SocketAddr) {
+        println!("[+] New connection from {}", peer);
 
             let (reader, mut writer) = stream.into_split();
                 let mut reader = BufReader::new(reader);
                     let mut line = String::new();
 
+                        if pow::is_enabled() {
+                                let prefix = pow::new_challenge();
+                                let _ = writer.write_all(pow::prompt(&prefix).as_bytes()).await;
+                                let _ = writer.flush().await;
+                                let pow_window = Duration::from_secs(server::config::CONFIG.pow_timeout_secs);
+                                let solved = match timeout(pow_window, reader.read_line(&mut line)).await {
+                                        Ok(Ok(n)) if n > 0 => pow::check(&prefix, line.trim()),
+                                        _ => false,
+                                };
+                                if !solved {
+                                        pow::forget(&prefix);
+                                        let _ = writer.write_all(b"POW_FAILED.\n").await;
+                                        let _ = writer.flush().await;
+                                        return;
+                                }
+                        }
+
                         writer.write_all(b"Enter your challenge_id:\n")
                                     .await.ok();
                             writer.flush().await.ok();
@@ -64,6 +81,11 @@ async fn handle_connection(stream: TcpStream) {
                                             if challenge_id.is_empty() {
                                                         return;
                                                             }
+                                            if let Err(reason) = roster::admit(&challenge_id) {
+                                                    let _ = writer.write_all(format!("INVALID_ID: {}\n", reason.reason()).as_bytes()).await;
+                                                    let _ = writer.flush().await;
+                                                    return;
+                                            }
 
                                                 session::init_challenge(&challenge_id);
 
@@ -105,15 +127,12 @@ async fn handle_connection(stream: TcpStream) {
                                                                                                                                                                             }
                                                                                                             };
 
-                                                                                                let correct_chacha_cipher_hex = match session::get_last_cipher(&challenge_id) {
-                                                                                                            Some(c) => c,
-                                                                                                                    None => {
+                                                                                                if session::get_last_cipher(&challenge_id).is_none() {
                                                                                                                                     let _ = writer.write_all(b"SERVER_ERROR.\n").await;
                                                                                                                                                 let _ = writer.flush().await;
                                                                                                                                                             session::clear_challenge(&challenge_id);
                                                                                                                                                                         return;
-                                                                                                                                                                                }
-                                                                                                                };
+                                                                                                                }
 
 
                                                                                                     line.clear();
@@ -133,27 +152,19 @@ async fn handle_connection(stream: TcpStream) {
 
                                                                                                                 let chacha_plaintext_input = line.trim();
                                                                                                                     
-                                                                                                                    let (is_match, user_chacha_cipher_hex) =
+                                                                                                                    let (is_match, _) =
                                                                                                                                 verify::verify_chacha_input(&challenge_id, chacha_plaintext_input);
 
-                                                                                                                        let server_match_chacha = is_match;
-                                                                                                                            let main_match_chacha = user_chacha_cipher_hex == correct_chacha_cipher_hex; 
-
                                                                                                                                 if !(is_match) { 
                                                                                                                                             session::clear_challenge(&challenge_id);
                                                                                                                                                     return;
                                                                                                                                                         }
 
-                                                                                                                                    let cham_nonce_hex_16 = format!("{}00000000", sess_hex_12);
 
-                                                                                                                                        let correct_cham_cipher_hex =
-                                                                                                                                                    match verify::generate_and_get_cham_cipher(&challenge_id, &cham_nonce_hex_16) {
-                                                                                                                                                                    Some(c) => c,
-                                                                                                                                                                                None => {
+                                                                                                                                        if verify::generate_and_get_cham_cipher(&challenge_id, &sess_hex_12).is_none() {
                                                                                                                                                                                                     session::clear_challenge(&challenge_id);
                                                                                                                                                                                                                     return;
-                                                                                                                                                                                                                                }
-                                                                                                                                                                            };
+                                                                                                                                                                            }
 
 
                                                                                                                                             line.clear();
@@ -173,26 +184,20 @@ async fn handle_connection(stream: TcpStream) {
 
                                                                                                                                                         let final_plaintext_input = line.trim();
                                                                                                                                                             
-                                                                                                                                                            let (is_match, user_cham_cipher_hex) =
-                                                                                                                                                                        verify::verify_cham_input(&challenge_id, final_plaintext_input, &cham_nonce_hex_16);
+                                                                                                                                                            let (is_match, _) =
+                                                                                                                                                                        verify::verify_cham_input(&challenge_id, final_plaintext_input, &sess_hex_12);
 
-                                                                                                                                                                let correct_cham_cipher_hex_main =
-                                                                                                                                                                            match session::get_last_cham_cipher(&challenge_id) {
-                                                                                                                                                                                            Some(c) => c,
-                                                                                                                                                                                                        None => {
+                                                                                                                                                                if session::get_last_cham_cipher(&challenge_id).is_none() {
                                                                                                                                                                                                                             session::clear_challenge(&challenge_id);
                                                                                                                                                                                                                                             return;
-                                                                                                                                                                                                                                                        }
-                                                                                                                                                                                                    };
-
-                                                                                                                                                                    let server_match_cham = is_match;
-                                                                                                                                                                        let main_match_cham = user_cham_cipher_hex == correct_cham_cipher_hex_main;
+                                                                                                                                                                                                    }
 
                                                                                                                                                                             if is_match {
                                                                                                                                                                                         let _ = writer
                                                                                                                                                                                                         .write_all(b"\nCONGRATULATIONS! AUTH SUCCESS\n")
                                                                                                                                                                                                                     .await;
                                                                                                                                                                                                 let _ = writer.flush().await;
+                                                                                                                                                                                                scoreboard::notify_solve(&challenge_id);
                                                                                                                                                                                                     } else {
                                                                                                                                                                                                                 session::clear_challenge(&challenge_id);
                                                                                                                                                                                                                         return;
@@ -202,15 +207,257 @@ async fn handle_connection(stream: TcpStream) {
 }
 
 
+// Organizer helpers for the stage-1 encoding:
+//   --encode [--v1] [--check] <text> | --encode-hex [--v1] [--check] <hex>
+//   --decode [--v1] [--check] <encoded>
+fn codec_cli(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+        let (flag, rest) = match args.split_first() {
+                Some((f, r)) if f.starts_with("--encode") || f == "--decode" => (f.as_str(), r),
+                _ => return Ok(false),
+        };
+        let (value, opts) = match rest.split_last() {
+                Some((v, o)) if o.iter().all(|o| o == "--v1" || o == "--check") => (v, o),
+                _ => return Err(format!("usage: {} [--v1] [--check] <value>", flag).into()),
+        };
+        let mode = if opts.iter().any(|o| o == "--v1") {
+                mixed_radix::EncodingMode::V1
+        } else {
+                mixed_radix::EncodingMode::Legacy
+        };
+        let codec = mixed_radix::DEFAULT_CODEC
+                .clone()
+                .with_mode(mode)
+                .with_checksum(opts.iter().any(|o| o == "--check"));
+        match flag {
+                "--encode" => println!("{}", codec.encode(value.as_bytes())),
+                "--encode-hex" => println!("{}", codec.encode(&hex::decode(value)?)),
+                "--decode" => {
+                        let bytes = codec.decode(value)?;
+                        match std::str::from_utf8(&bytes) {
+                                Ok(text) if !text.chars().any(char::is_control) => println!("{}", text),
+                                _ => println!("hex:{}", hex::encode(&bytes)),
+                        }
+                }
+                _ => return Err(format!("unknown option {}", flag).into()),
+        }
+        Ok(true)
+}
+
+// `--chacha-spec [custom|standard|<file.json>]` prints the validated spec,
+// `--chacha-solver ...` the matching Python reference solver, and
+// `--chacha-encrypt <spec> <key> <nonce-hex> <text>` the ciphertext a stage
+// built on that spec would issue, to check the solver against.
+fn chacha_cli(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+        let (flag, rest) = match args.split_first() {
+                Some((f, r)) if f == "--chacha-spec" || f == "--chacha-solver" || f == "--chacha-encrypt" => (f.as_str(), r),
+                _ => return Ok(false),
+        };
+        let (rest, encrypt_args) = match (flag, rest) {
+                ("--chacha-encrypt", [spec, key, nonce, text]) => (std::slice::from_ref(spec), Some((key, nonce, text))),
+                ("--chacha-encrypt", _) => return Err("usage: --chacha-encrypt custom|standard|<spec.json> <key> <nonce-hex> <text>".into()),
+                _ => (rest, None),
+        };
+        let spec = match rest {
+                [] => ChaChaSpec::from(ChaChaVariant::Custom),
+                [v] if v == "custom" => ChaChaSpec::from(ChaChaVariant::Custom),
+                [v] if v == "standard" => ChaChaSpec::from(ChaChaVariant::Standard),
+                [path] => ChaChaSpec::from_json(&std::fs::read_to_string(path)?)?,
+                _ => return Err(format!("usage: {} [custom|standard|<spec.json>]", flag).into()),
+        };
+        if let Some((key, nonce, text)) = encrypt_args {
+                let ciphertext = spec.encrypt(key.as_bytes(), &hex::decode(nonce)?, chacha_logic::INITIAL_COUNTER, text.as_bytes())?;
+                println!("{}", hex::encode(ciphertext));
+        } else if flag == "--chacha-spec" {
+                println!("{}", spec.to_json());
+        } else {
+                print!("{}", spec.python_solver());
+        }
+        Ok(true)
+}
+
+// `--cham-mode ecb|cbc|cfb|ofb encrypt|decrypt <variant> <key-hex> [iv-hex]`
+// runs one of the classic block modes from cham_modes over stdin and
+// writes the raw result to stdout, to prepare data for stages that hand
+// players a ciphertext. Variants are 64-128, 128-128, 128-256 and challenge.
+fn cham_mode_cli(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+        let rest = match args.split_first() {
+                Some((f, r)) if f == "--cham-mode" => r,
+                _ => return Ok(false),
+        };
+        let usage = "usage: --cham-mode ecb|cbc|cfb|ofb encrypt|decrypt 64-128|128-128|128-256|challenge <key-hex> [iv-hex]";
+        let (mode, direction, variant, key, iv) = match rest {
+                [m, d, v, k] => (m.as_str(), d.as_str(), v.as_str(), hex::decode(k)?, Vec::new()),
+                [m, d, v, k, iv] => (m.as_str(), d.as_str(), v.as_str(), hex::decode(k)?, hex::decode(iv)?),
+                _ => return Err(usage.into()),
+        };
+        let variant = match variant {
+                "64-128" => ChamVariant::Cham64_128,
+                "128-128" => ChamVariant::Cham128_128,
+                "128-256" => ChamVariant::Cham128_256,
+                "challenge" => ChamVariant::Challenge,
+                _ => return Err(format!("unknown CHAM variant {}", variant).into()),
+        };
+        let mut input = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin().lock(), &mut input)?;
+        let output = match (mode, direction) {
+                ("ecb", "encrypt") => cham_modes::ecb_encrypt(variant, &key, &input)?,
+                ("ecb", "decrypt") => cham_modes::ecb_decrypt(variant, &key, &input)?,
+                ("cbc", "encrypt") => cham_modes::cbc_encrypt(variant, &key, &iv, &input)?,
+                ("cbc", "decrypt") => cham_modes::cbc_decrypt(variant, &key, &iv, &input)?,
+                ("cfb", "encrypt") => cham_modes::cfb_encrypt(variant, &key, &iv, &input)?,
+                ("cfb", "decrypt") => cham_modes::cfb_decrypt(variant, &key, &iv, &input)?,
+                ("ofb", "encrypt" | "decrypt") => cham_modes::ofb_apply(variant, &key, &iv, &input)?,
+                _ => return Err(usage.into()),
+        };
+        std::io::Write::write_all(&mut std::io::stdout().lock(), &output)?;
+        Ok(true)
+}
+
+// `--cham-encrypt <key> <nonce-hex> <text>` prints the hex ciphertext
+// under the stage-4 CHAM-CTR layout: a 12-byte nonce prefixing a 32-bit
+// counter from 1, as ChamStage and `--trace cham` take it. For
+// solve/custom_Cham-Ctf.py pass the first 12 bytes of its NONCE_HEX; the
+// rest never reached the cipher. Without `text`, `--cham-encrypt` and
+// `--cham-decrypt` stream stdin to stdout as raw bytes instead.
+fn cham_cli(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+        let (flag, rest) = match args.split_first() {
+                Some((f, r)) if f == "--cham-encrypt" || f == "--cham-decrypt" => (f.as_str(), r),
+                _ => return Ok(false),
+        };
+        let (key, nonce) = match rest {
+                [key, nonce, text] if flag == "--cham-encrypt" => {
+                        let ciphertext = cham_logic::encrypt(key.as_bytes(), &hex::decode(nonce)?, text.as_bytes())?;
+                        println!("{}", hex::encode(ciphertext));
+                        return Ok(true);
+                }
+                [key, nonce] => (key, hex::decode(nonce)?),
+                _ => return Err("usage: --cham-encrypt <key> <nonce-hex> [text] | --cham-decrypt <key> <nonce-hex>".into()),
+        };
+        if nonce.len() != 12 {
+                return Err(format!("CHAM nonce must be 12 bytes, got {}", nonce.len()).into());
+        }
+        let ctr = ChamCtr::new(ChamKey::new(key.as_bytes())?, &nonce, cham_logic::INITIAL_COUNTER)?;
+        let (stdin, stdout) = (std::io::stdin(), std::io::stdout());
+        if flag == "--cham-encrypt" {
+                let mut writer = CtrWriter::new(stdout.lock(), ctr);
+                std::io::copy(&mut stdin.lock(), &mut writer)?;
+                std::io::Write::flush(&mut writer.into_inner())?;
+        } else {
+                std::io::copy(&mut CtrReader::new(stdin.lock(), ctr), &mut stdout.lock())?;
+        }
+        Ok(true)
+}
+
+// `--block-cipher <cipher> encrypt|decrypt <key-hex> <block-hex>` runs one
+// LEA, ARIA or SEED block, and `--block-cipher <cipher> ctr <key-hex>
+// <prefix-hex> <data-hex>` their CTR mode from counter 1; both print hex.
+// Ciphers are lea128|lea192|lea256|aria128|aria192|aria256|seed128, or
+// lea|aria|seed-challenge for the tweaked variants new stages would use.
+fn block_cipher_cli(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+        enum Cipher { Lea(LeaVariant), Aria(AriaVariant), Seed(SeedVariant) }
+        let rest = match args.split_first() {
+                Some((f, r)) if f == "--block-cipher" => r,
+                _ => return Ok(false),
+        };
+        let usage = "usage: --block-cipher <cipher> encrypt|decrypt <key-hex> <block-hex> | --block-cipher <cipher> ctr <key-hex> <prefix-hex> <data-hex>";
+        let (cipher, op, key, prefix, data) = match rest {
+                [c, op, k, b] if op != "ctr" => (c.as_str(), op.as_str(), hex::decode(k)?, None, hex::decode(b)?),
+                [c, op, k, p, d] if op == "ctr" => (c.as_str(), op.as_str(), hex::decode(k)?, Some(hex::decode(p)?), hex::decode(d)?),
+                _ => return Err(usage.into()),
+        };
+        let cipher = match cipher {
+                "lea128" => Cipher::Lea(LeaVariant::Lea128),
+                "lea192" => Cipher::Lea(LeaVariant::Lea192),
+                "lea256" => Cipher::Lea(LeaVariant::Lea256),
+                "lea-challenge" => Cipher::Lea(LeaVariant::Challenge),
+                "aria128" => Cipher::Aria(AriaVariant::Aria128),
+                "aria192" => Cipher::Aria(AriaVariant::Aria192),
+                "aria256" => Cipher::Aria(AriaVariant::Aria256),
+                "aria-challenge" => Cipher::Aria(AriaVariant::Challenge),
+                "seed128" => Cipher::Seed(SeedVariant::Seed128),
+                "seed-challenge" => Cipher::Seed(SeedVariant::Challenge),
+                _ => return Err(format!("unknown block cipher {}", cipher).into()),
+        };
+        let out = match (cipher, op, prefix) {
+                (Cipher::Lea(v), "encrypt", None) => lea_logic::lea_encrypt_block(v, &key, &data)?.to_vec(),
+                (Cipher::Lea(v), "decrypt", None) => lea_logic::lea_decrypt_block(v, &key, &data)?.to_vec(),
+                (Cipher::Lea(v), "ctr", Some(p)) => lea_logic::lea_encrypt_ctr(v, &key, &p, 1, &data)?,
+                (Cipher::Aria(v), "encrypt", None) => aria_logic::aria_encrypt_block(v, &key, &data)?.to_vec(),
+                (Cipher::Aria(v), "decrypt", None) => aria_logic::aria_decrypt_block(v, &key, &data)?.to_vec(),
+                (Cipher::Aria(v), "ctr", Some(p)) => aria_logic::aria_encrypt_ctr(v, &key, &p, 1, &data)?,
+                (Cipher::Seed(v), "encrypt", None) => seed_logic::seed_encrypt_block(v, &key, &data)?.to_vec(),
+                (Cipher::Seed(v), "decrypt", None) => seed_logic::seed_decrypt_block(v, &key, &data)?.to_vec(),
+                (Cipher::Seed(v), "ctr", Some(p)) => seed_logic::seed_encrypt_ctr(v, &key, &p, 1, &data)?,
+                _ => return Err(usage.into()),
+        };
+        println!("{}", hex::encode(out));
+        Ok(true)
+}
+
+// `--trace chacha|cham <key> <nonce-hex> <counter>` dumps one block round
+// by round as JSON: the stage-3 keystream block at `counter`, or the
+// stage-4 encryption of the counter block `nonce || BE32(counter)`. The key
+// is taken as text, like the reference solvers.
+fn trace_cli(args: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+        let rest = match args.split_first() {
+                Some((f, r)) if f == "--trace" => r,
+                _ => return Ok(false),
+        };
+        let (cipher, key, nonce, counter) = match rest {
+                [c, k, n, ctr] => (c.as_str(), k.as_bytes(), hex::decode(n)?, ctr.parse::<u32>()?),
+                _ => return Err("usage: --trace chacha|cham <key> <nonce-hex> <counter>".into()),
+        };
+        let json = match cipher {
+                "chacha" => chacha_logic::chacha20_block_trace(ChaChaVariant::Custom, key, counter, &nonce)?.to_json(),
+                "cham" => {
+                        if nonce.len() != 12 {
+                                return Err(format!("CHAM nonce must be 12 bytes, got {}", nonce.len()).into());
+                        }
+                        let mut block = nonce;
+                        block.extend_from_slice(&counter.to_be_bytes());
+                        cham_logic::cham128_256_encrypt_block_trace(key, &block, ChamVariant::Challenge.rounds())?.to_json()
+                }
+                _ => return Err(format!("unknown cipher {}, expected chacha or cham", cipher).into()),
+        };
+        println!("{}", json);
+        Ok(true)
+}
+
 use server::verify::FlagSvc;
+use server::verify::chacha_logic::ChaChaVariant;
+use server::verify::chacha_spec::ChaChaSpec;
+use server::verify::{aria_logic, chacha_logic, cham_logic, cham_modes, lea_logic, seed_logic};
+use server::verify::aria_logic::AriaVariant;
+use server::verify::lea_logic::LeaVariant;
+use server::verify::seed_logic::SeedVariant;
+use server::verify::cham_logic::{ChamCtr, ChamKey, ChamVariant, CtrReader, CtrWriter};
 #[cfg(feature = "server")]
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-        let grpc_addr = "0.0.0.0:5000".parse()?;
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if codec_cli(&args)? || chacha_cli(&args)? || cham_cli(&args)? || cham_mode_cli(&args)? || block_cipher_cli(&args)? || trace_cli(&args)? {
+                return Ok(());
+        }
+        server::config::load().map_err(|e| format!("refusing to start: {}", e))?;
+        verify::load_stage_keys();
+        if args.first().map(String::as_str) == Some("--self-test") {
+                selftest::run().await?;
+                println!("Self-test passed");
+                return Ok(());
+        }
+        if server::config::CONFIG.self_test_on_start {
+                selftest::run()
+                        .await
+                        .map_err(|e| format!("self-test failed, refusing to start: {}", e))?;
+        }
+
+        let grpc_addr: SocketAddr = "0.0.0.0:5000".parse()?;
+        let grpc_listener = TcpListener::bind(grpc_addr).await?;
             tokio::spawn(async move {
                         match Server::builder()
-                                        .add_service(PanicVerifierServer::new(FlagSvc::default()))
-                                                    .serve(grpc_addr)
+                                        .layer(acl::AclLayer)
+                                        .add_service(PanicVerifierServer::new(FlagSvc))
+                                                    .serve_with_incoming(proxy_proto::incoming(grpc_listener))
                                                                 .await
                                                                             {
                                                                                                 Ok(_) => println!("gRPC server started on 5000"),
@@ -218,14 +465,51 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                                                                                                             }
                             });
 
+                let (allow, deny) = acl::reload()?;
+                if allow + deny > 0 {
+                        println!("Loaded IP rules: {} allow, {} deny", allow, deny);
+                }
+                tokio::spawn(async {
+                        let mut hup = match signal(SignalKind::hangup()) {
+                                Ok(s) => s,
+                                Err(e) => {
+                                        eprintln!("SIGHUP handler unavailable: {}", e);
+                                        return;
+                                }
+                        };
+                        while hup.recv().await.is_some() {
+                                match acl::reload() {
+                                        Ok((allow, deny)) => println!("[acl] reloaded: {} allow, {} deny", allow, deny),
+                                        Err(e) => eprintln!("[acl] reload failed, keeping old rules: {}", e),
+                                }
+                                if let Some(path) = &server::config::CONFIG.roster_file {
+                                        match roster::load(path) {
+                                                Ok(teams) => println!("[roster] reloaded: {} teams", teams),
+                                                Err(e) => eprintln!("[roster] reload failed, keeping old roster: {}", e),
+                                        }
+                                }
+                        }
+                });
+
+                if let Some(path) = &server::config::CONFIG.roster_file {
+                        let teams = roster::load(path)?;
+                        println!("Loaded roster with {} teams", teams);
+                }
+
+                tokio::spawn(scoreboard::run_outbox_flusher());
+
                 let challenge_addr = "0.0.0.0:5001";
                     let listener = TcpListener::bind(challenge_addr).await?;
                         println!("Challenge server running on 5001");
 
                             loop {
-                                        let (stream, _) = listener.accept().await?;
+                                        let (mut stream, _) = listener.accept().await?;
                                                 tokio::spawn(async move {
-                                                                handle_connection(stream).await;
+                                                                match proxy_proto::client_addr(&mut stream).await {
+                                                                        Ok(peer) if !acl::admit(&peer.ip(), "tcp") => {}
+                                                                        Ok(peer) => handle_connection(stream, peer).await,
+                                                                        Err(e) => eprintln!("[-] Connection dropped: {}", e),
+                                                                }
                                                                         });
                                                     }
 }
@@ -0,0 +1,240 @@
+use ipnet::IpNet;
+use once_cell::sync::Lazy;
+use std::fs;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::transport::server::TcpConnectInfo;
+use tonic::Status;
+use tower::{Layer, Service};
+
+use super::config::{parse_nets, CONFIG};
+
+#[derive(Default)]
+struct Rules {
+        allow: Vec<IpNet>,
+        deny: Vec<IpNet>,
+}
+
+static RULES: Lazy<RwLock<Rules>> = Lazy::new(|| RwLock::new(Rules::default()));
+static BLOCKED: AtomicU64 = AtomicU64::new(0);
+
+// Rules come from IP_ALLOW / IP_DENY plus, when set, the IP_ACL_FILE with
+// `allow <cidr>` / `deny <cidr>` lines. The file is re-read on SIGHUP, so
+// hosts can be blocked mid-event without dropping live sessions.
+pub fn reload() -> Result<(usize, usize), String> {
+        let base = Rules {
+                allow: CONFIG.ip_allow.clone(),
+                deny: CONFIG.ip_deny.clone(),
+        };
+        reload_from(base, CONFIG.ip_acl_file.as_deref())
+}
+
+// The live rules are only swapped once the whole file has parsed, so a bad
+// edit followed by SIGHUP leaves the previous rules in force.
+fn reload_from(mut rules: Rules, path: Option<&str>) -> Result<(usize, usize), String> {
+        if let Some(path) = path {
+                let data = fs::read_to_string(path)
+                        .map_err(|e| format!("cannot read ACL {}: {}", path, e))?;
+                for (lineno, raw) in data.lines().enumerate() {
+                        let line = raw.trim();
+                        if line.is_empty() || line.starts_with('#') {
+                                continue;
+                        }
+                        let (verb, nets) = line.split_once(char::is_whitespace)
+                                .ok_or_else(|| format!("ACL line {}: expected `allow|deny <cidr>`", lineno + 1))?;
+                        let nets = parse_nets(nets).map_err(|e| format!("ACL line {}: {}", lineno + 1, e))?;
+                        match verb {
+                                "allow" => rules.allow.extend(nets),
+                                "deny" => rules.deny.extend(nets),
+                                _ => return Err(format!("ACL line {}: unknown verb `{}`", lineno + 1, verb)),
+                        }
+                }
+        }
+        let counts = (rules.allow.len(), rules.deny.len());
+        *RULES.write().unwrap() = rules;
+        Ok(counts)
+}
+
+impl Rules {
+        // Deny entries win; a non-empty allow list turns the server into
+        // venue-only mode where everything else is refused.
+        fn allows(&self, ip: &IpAddr) -> bool {
+                if self.deny.iter().any(|n| n.contains(ip)) {
+                        return false;
+                }
+                self.allow.is_empty() || self.allow.iter().any(|n| n.contains(ip))
+        }
+}
+
+pub fn is_allowed(ip: &IpAddr) -> bool {
+        RULES.read().unwrap().allows(ip)
+}
+
+pub fn admit(ip: &IpAddr, listener: &str) -> bool {
+        if is_allowed(ip) {
+                return true;
+        }
+        let total = BLOCKED.fetch_add(1, Ordering::Relaxed) + 1;
+        println!("[acl] blocked {} on {} ({} blocked so far)", ip, listener, total);
+        false
+}
+
+#[derive(Clone, Default)]
+pub struct AclLayer;
+
+impl<S> Layer<S> for AclLayer {
+        type Service = AclService<S>;
+
+        fn layer(&self, inner: S) -> Self::Service {
+                AclService { inner }
+        }
+}
+
+#[derive(Clone)]
+pub struct AclService<S> {
+        inner: S,
+}
+
+impl<S, B> Service<http::Request<B>> for AclService<S>
+where
+        S: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+        S::Future: Send + 'static,
+{
+        type Response = S::Response;
+        type Error = S::Error;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+                self.inner.poll_ready(cx)
+        }
+
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+                let remote = req
+                        .extensions()
+                        .get::<TcpConnectInfo>()
+                        .and_then(|info| info.remote_addr);
+                if let Some(addr) = remote {
+                        if !admit(&addr.ip(), "gRPC") {
+                                let resp = Status::permission_denied("address blocked").to_http();
+                                return Box::pin(async move { Ok(resp) });
+                        }
+                }
+                Box::pin(self.inner.call(req))
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        fn rules(allow: &str, deny: &str) -> Rules {
+                Rules { allow: parse_nets(allow).unwrap(), deny: parse_nets(deny).unwrap() }
+        }
+
+        fn ip(s: &str) -> IpAddr {
+                s.parse().unwrap()
+        }
+
+        #[test]
+        fn empty_rules_allow_everyone() {
+                let r = rules("", "");
+                assert!(r.allows(&ip("203.0.113.9")));
+                assert!(r.allows(&ip("2001:db8::1")));
+        }
+
+        #[test]
+        fn empty_allow_list_only_applies_denies() {
+                let r = rules("", "198.51.100.0/24");
+                assert!(!r.allows(&ip("198.51.100.77")));
+                assert!(r.allows(&ip("198.51.101.1")));
+        }
+
+        #[test]
+        fn allow_list_refuses_everything_else() {
+                let r = rules("10.20.0.0/16, 2001:db8:aa::/48", "");
+                assert!(r.allows(&ip("10.20.255.1")));
+                assert!(!r.allows(&ip("10.21.0.1")));
+                assert!(r.allows(&ip("2001:db8:aa:ffff::1")));
+                assert!(!r.allows(&ip("2001:db8:ab::1")));
+                assert!(!r.allows(&ip("203.0.113.9")));
+        }
+
+        #[test]
+        fn deny_wins_over_allow() {
+                let r = rules("10.0.0.0/8, 2001:db8::/32", "10.6.6.0/24, 2001:db8:bad::/48, 10.9.9.9");
+                assert!(r.allows(&ip("10.6.7.1")));
+                assert!(!r.allows(&ip("10.6.6.200")));
+                assert!(!r.allows(&ip("10.9.9.9")));
+                assert!(r.allows(&ip("10.9.9.10")));
+                assert!(!r.allows(&ip("2001:db8:bad::5")));
+                assert!(r.allows(&ip("2001:db8:bae::5")));
+        }
+
+        #[test]
+        fn families_do_not_cross_match() {
+                let r = rules("0.0.0.0/0", "");
+                assert!(r.allows(&ip("192.0.2.1")));
+                assert!(!r.allows(&ip("2001:db8::1")));
+                let r = rules("", "::/0");
+                assert!(r.allows(&ip("192.0.2.1")));
+                assert!(!r.allows(&ip("::1")));
+        }
+
+        // The reload tests share the live RULES table, so they take turns.
+        static LIVE: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+        fn acl_file(name: &str, contents: &str) -> String {
+                let path = std::env::temp_dir()
+                        .join(format!("acl-{}-{}.txt", std::process::id(), name))
+                        .to_string_lossy()
+                        .into_owned();
+                fs::write(&path, contents).unwrap();
+                path
+        }
+
+        #[test]
+        fn reload_applies_a_good_file() {
+                let _live = LIVE.lock().unwrap();
+                let path = acl_file("good", "# venue\nallow 10.20.0.0/16, 2001:db8:aa::/48\n\ndeny 10.20.6.6\n");
+                assert_eq!(reload_from(rules("192.0.2.0/24", ""), Some(&path)), Ok((3, 1)));
+                assert!(is_allowed(&ip("10.20.1.1")));
+                assert!(is_allowed(&ip("192.0.2.7")));
+                assert!(!is_allowed(&ip("10.20.6.6")));
+                assert!(!is_allowed(&ip("203.0.113.9")));
+                fs::remove_file(path).unwrap();
+        }
+
+        #[test]
+        fn reload_rejects_a_malformed_line_and_keeps_the_previous_rules() {
+                let _live = LIVE.lock().unwrap();
+                let good = acl_file("before-bad", "deny 198.51.100.0/24\n");
+                assert_eq!(reload_from(Rules::default(), Some(&good)), Ok((0, 1)));
+
+                let bad = acl_file("bad", "deny 203.0.113.0/24\nblock 10.0.0.0/8\n");
+                let err = reload_from(Rules::default(), Some(&bad)).unwrap_err();
+                assert_eq!(err, "ACL line 2: unknown verb `block`");
+                let bad_cidr = acl_file("bad-cidr", "allow 10.0.0.0/33\n");
+                assert!(reload_from(Rules::default(), Some(&bad_cidr)).unwrap_err().starts_with("ACL line 1: "));
+
+                assert!(!is_allowed(&ip("198.51.100.1")));
+                assert!(is_allowed(&ip("203.0.113.9")));
+                assert!(is_allowed(&ip("10.1.1.1")));
+                for path in [good, bad, bad_cidr] {
+                        fs::remove_file(path).unwrap();
+                }
+        }
+
+        #[test]
+        fn reload_of_a_missing_file_is_an_error_and_keeps_the_previous_rules() {
+                let _live = LIVE.lock().unwrap();
+                assert_eq!(reload_from(rules("", "198.51.100.0/24"), None), Ok((0, 1)));
+                let err = reload_from(Rules::default(), Some("/nonexistent/acl.txt")).unwrap_err();
+                assert!(err.starts_with("cannot read ACL /nonexistent/acl.txt"), "{}", err);
+                assert!(!is_allowed(&ip("198.51.100.1")));
+        }
+}
@@ -0,0 +1,185 @@
+use ipnet::IpNet;
+use once_cell::sync::{Lazy, OnceCell};
+use std::env;
+
+// Runtime settings read from the environment once at startup.
+// Everything here is optional; an unset variable keeps the event defaults,
+// but a value that is set and malformed stops the server from starting.
+pub struct ServerConfig {
+        pub scoreboard_url: Option<String>,
+        pub scoreboard_token: Option<String>,
+        pub scoreboard_mode: ScoreboardMode,
+        pub scoreboard_challenge: String,
+        pub scoreboard_retries: u32,
+        pub scoreboard_outbox: String,
+        pub scoreboard_flush_secs: u64,
+        pub pow_difficulty: u32,
+        pub pow_timeout_secs: u64,
+        pub roster_file: Option<String>,
+        pub proxy_protocol: bool,
+        pub proxy_trusted: Vec<IpNet>,
+        pub ip_allow: Vec<IpNet>,
+        pub ip_deny: Vec<IpNet>,
+        pub ip_acl_file: Option<String>,
+        pub self_test_on_start: bool,
+}
+
+// What SCOREBOARD_URL speaks: the crate's own solve webhook, or CTFd's
+// submissions API with the numeric challenge id from
+// SCOREBOARD_CTFD_CHALLENGE_ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScoreboardMode {
+        Webhook,
+        Ctfd { challenge_id: u64 },
+}
+
+static LOADED: OnceCell<ServerConfig> = OnceCell::new();
+
+// main() calls load() before anything reads CONFIG, so the panic below is
+// only reachable from code that skips it.
+pub static CONFIG: Lazy<&'static ServerConfig> = Lazy::new(|| {
+        LOADED.get_or_init(|| ServerConfig::from_env().unwrap_or_else(|e| panic!("invalid configuration: {}", e)))
+});
+
+pub fn load() -> Result<(), String> {
+        let config = ServerConfig::from_env()?;
+        let _ = LOADED.set(config);
+        Ok(())
+}
+
+fn env_opt(name: &str) -> Option<String> {
+        env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn parse_or<T: std::str::FromStr>(name: &str, raw: Option<String>, default: T) -> Result<T, String> {
+        match raw {
+                Some(v) => v.parse().map_err(|_| format!("{}: invalid value `{}`", name, v)),
+                None => Ok(default),
+        }
+}
+
+fn parse_flag(name: &str, raw: Option<String>, default: bool) -> Result<bool, String> {
+        match raw.as_deref() {
+                Some("1") | Some("true") | Some("yes") | Some("on") => Ok(true),
+                Some("0") | Some("false") | Some("no") | Some("off") => Ok(false),
+                Some(v) => Err(format!("{}: expected a boolean (1/0, true/false, yes/no, on/off), got `{}`", name, v)),
+                None => Ok(default),
+        }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String> {
+        parse_or(name, env_opt(name), default)
+}
+
+fn env_flag(name: &str, default: bool) -> Result<bool, String> {
+        parse_flag(name, env_opt(name), default)
+}
+
+fn scoreboard_mode(mode: Option<String>, ctfd_challenge: Option<String>) -> Result<ScoreboardMode, String> {
+        match mode.as_deref() {
+                None | Some("webhook") => Ok(ScoreboardMode::Webhook),
+                Some("ctfd") => match ctfd_challenge {
+                        Some(_) => Ok(ScoreboardMode::Ctfd {
+                                challenge_id: parse_or("SCOREBOARD_CTFD_CHALLENGE_ID", ctfd_challenge, 0)?,
+                        }),
+                        None => Err("SCOREBOARD_MODE=ctfd needs SCOREBOARD_CTFD_CHALLENGE_ID".to_string()),
+                },
+                Some(other) => Err(format!("SCOREBOARD_MODE: expected webhook or ctfd, got `{}`", other)),
+        }
+}
+
+// Comma-separated CIDRs; a bare address is taken as a single host.
+pub fn parse_nets(list: &str) -> Result<Vec<IpNet>, String> {
+        list.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                        s.parse::<IpNet>()
+                                .or_else(|_| s.parse::<std::net::IpAddr>().map(IpNet::from))
+                                .map_err(|_| format!("invalid CIDR `{}`", s))
+                })
+                .collect()
+}
+
+// A list that fails to parse is an error rather than an empty list: an
+// empty deny list lets everyone in, and an empty allow list means "allow all".
+fn env_nets(name: &str) -> Result<Vec<IpNet>, String> {
+        let raw = env_opt(name).unwrap_or_default();
+        parse_nets(&raw).map_err(|e| format!("{}: {}", name, e))
+}
+
+impl ServerConfig {
+        pub fn from_env() -> Result<Self, String> {
+                let config = ServerConfig {
+                        scoreboard_url: env_opt("SCOREBOARD_URL"),
+                        scoreboard_token: env_opt("SCOREBOARD_TOKEN"),
+                        scoreboard_mode: scoreboard_mode(env_opt("SCOREBOARD_MODE"), env_opt("SCOREBOARD_CTFD_CHALLENGE_ID"))?,
+                        scoreboard_challenge: env_or("SCOREBOARD_CHALLENGE", "Rusty Protocol".to_string())?,
+                        scoreboard_retries: env_or("SCOREBOARD_RETRIES", 3)?,
+                        scoreboard_outbox: env_or("SCOREBOARD_OUTBOX", "scoreboard_outbox.jsonl".to_string())?,
+                        scoreboard_flush_secs: env_or("SCOREBOARD_FLUSH_SECS", 60)?,
+                        pow_difficulty: env_or("POW_DIFFICULTY", 0)?,
+                        pow_timeout_secs: env_or("POW_TIMEOUT_SECS", 60)?,
+                        roster_file: env_opt("ROSTER_FILE"),
+                        proxy_protocol: env_flag("PROXY_PROTOCOL", false)?,
+                        proxy_trusted: env_nets("PROXY_TRUSTED")?,
+                        ip_allow: env_nets("IP_ALLOW")?,
+                        ip_deny: env_nets("IP_DENY")?,
+                        ip_acl_file: env_opt("IP_ACL_FILE"),
+                        self_test_on_start: env_flag("SELF_TEST_ON_START", true)?,
+                };
+                // CTFd team ids come from the roster's third column.
+                if matches!(config.scoreboard_mode, ScoreboardMode::Ctfd { .. }) && config.roster_file.is_none() {
+                        return Err("SCOREBOARD_MODE=ctfd needs a ROSTER_FILE with CTFd team ids".to_string());
+                }
+                Ok(config)
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        #[test]
+        fn parses_cidrs_and_bare_addresses() {
+                let nets = parse_nets(" 10.0.0.0/8 ,192.0.2.7,, 2001:db8::/32,::1 ").unwrap();
+                let expected: Vec<IpNet> = ["10.0.0.0/8", "192.0.2.7/32", "2001:db8::/32", "::1/128"]
+                        .iter()
+                        .map(|n| n.parse().unwrap())
+                        .collect();
+                assert_eq!(nets, expected);
+                assert_eq!(parse_nets("").unwrap(), Vec::<IpNet>::new());
+        }
+
+        #[test]
+        fn one_bad_entry_fails_the_whole_list() {
+                assert_eq!(parse_nets("10.0.0.0/8, 10.0.0.0/33"), Err("invalid CIDR `10.0.0.0/33`".to_string()));
+                assert!(parse_nets("192.0.2.1, example.org").is_err());
+        }
+
+        #[test]
+        fn malformed_numbers_are_errors_not_defaults() {
+                assert_eq!(parse_or("POW_DIFFICULTY", Some("2O".to_string()), 0u32), Err("POW_DIFFICULTY: invalid value `2O`".to_string()));
+                assert!(parse_or("SCOREBOARD_RETRIES", Some("-1".to_string()), 3u32).is_err());
+                assert_eq!(parse_or("POW_DIFFICULTY", Some("20".to_string()), 0u32), Ok(20));
+                assert_eq!(parse_or("POW_DIFFICULTY", None, 0u32), Ok(0));
+        }
+
+        #[test]
+        fn scoreboard_mode_needs_a_numeric_ctfd_challenge() {
+                let s = |v: &str| Some(v.to_string());
+                assert_eq!(scoreboard_mode(None, None), Ok(ScoreboardMode::Webhook));
+                assert_eq!(scoreboard_mode(s("ctfd"), s("7")), Ok(ScoreboardMode::Ctfd { challenge_id: 7 }));
+                assert!(scoreboard_mode(s("ctfd"), None).is_err());
+                assert!(scoreboard_mode(s("ctfd"), s("seven")).is_err());
+                assert!(scoreboard_mode(s("CTFd-ish"), s("7")).is_err());
+        }
+
+        #[test]
+        fn malformed_flags_are_errors_not_defaults() {
+                assert!(parse_flag("PROXY_PROTOCOL", Some("ture".to_string()), false).is_err());
+                assert_eq!(parse_flag("PROXY_PROTOCOL", Some("on".to_string()), false), Ok(true));
+                assert_eq!(parse_flag("SELF_TEST_ON_START", Some("0".to_string()), true), Ok(false));
+                assert_eq!(parse_flag("SELF_TEST_ON_START", None, true), Ok(true));
+        }
+}
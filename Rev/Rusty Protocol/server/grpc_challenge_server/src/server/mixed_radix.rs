@@ -0,0 +1,484 @@
+use num_bigint::BigUint;
+use num_integer::Integer;
+use num_traits::{One, ToPrimitive, Zero};
+use once_cell::sync::Lazy;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{RwLock, RwLockReadGuard};
+
+use super::secret_provider::{ALLOWED_BASES, KEY_BYTES, SUPER_ALPHABET};
+
+// The stage-1 codec built from the event constants in secret_provider.rs.
+pub static DEFAULT_CODEC: Lazy<MixedRadixCodec> = Lazy::new(|| {
+        MixedRadixCodec::new(KEY_BYTES, ALLOWED_BASES, SUPER_ALPHABET)
+                .expect("event codec constants are invalid")
+});
+
+pub fn prf32(key: &[u8], ctx: &[u8]) -> u32 {
+        let mut hasher = Sha256::new();
+        hasher.update(key);
+        hasher.update(ctx);
+        let result = hasher.finalize();
+        u32::from_be_bytes(result[0..4].try_into().expect("Hash slice error"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+        EmptyKey,
+        NoBases,
+        BaseTooSmall(u32),
+        BaseTooLarge { base: u32, alphabet_len: usize },
+        DuplicateSymbol(char),
+}
+
+impl fmt::Display for CodecError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        CodecError::EmptyKey => write!(f, "codec key must not be empty"),
+                        CodecError::NoBases => write!(f, "at least one base is required"),
+                        CodecError::BaseTooSmall(b) => write!(f, "base {} is below 2", b),
+                        CodecError::BaseTooLarge { base, alphabet_len } => {
+                                write!(f, "base {} exceeds the {}-symbol alphabet", base, alphabet_len)
+                        }
+                        CodecError::DuplicateSymbol(c) => write!(f, "symbol {:?} appears twice in the alphabet", c),
+                }
+        }
+}
+
+impl std::error::Error for CodecError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+        Empty,
+        InvalidChar { index: usize, ch: char },
+        MissingChecksum,
+        BadChecksum,
+}
+
+impl fmt::Display for DecodeError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        DecodeError::Empty => write!(f, "empty input"),
+                        DecodeError::InvalidChar { index, ch } => {
+                                write!(f, "character {:?} at index {} is not in that position's alphabet", ch, index)
+                        }
+                        DecodeError::MissingChecksum => write!(f, "malformed encoding: too short to carry a checksum"),
+                        DecodeError::BadChecksum => write!(f, "malformed encoding: checksum mismatch"),
+                }
+        }
+}
+
+impl std::error::Error for DecodeError {}
+
+// How the byte string maps onto the number being encoded.
+// `Legacy` is the original stage-1 format: the input is read as one big
+// integer, so leading 0x00 bytes are lost. `V1` keeps them the Base58 way,
+// emitting one zero digit per leading zero byte ahead of the number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EncodingMode {
+        #[default]
+        Legacy,
+        V1,
+}
+
+// Position-keyed mixed-radix encoding: every output position draws its base
+// from `bases` and its digit alphabet from a shuffle of `super_alphabet`,
+// both chosen by a PRF over `key` and the position (counted from the LSB).
+#[derive(Debug)]
+pub struct MixedRadixCodec {
+        key: Vec<u8>,
+        bases: Vec<u32>,
+        super_alphabet: Vec<char>,
+        mode: EncodingMode,
+        checksum: bool,
+        positions: RwLock<Vec<Position>>,
+}
+
+#[derive(Debug, Clone)]
+struct Position {
+        base: u32,
+        alphabet: Vec<char>,
+}
+
+// Position table handed to encode/decode: the shared cache, or for inputs
+// longer than the cache holds, a private copy extended past the cap.
+enum Table<'a> {
+        Cached(RwLockReadGuard<'a, Vec<Position>>),
+        Extended(Vec<Position>),
+}
+
+impl Deref for Table<'_> {
+        type Target = [Position];
+
+        fn deref(&self) -> &[Position] {
+                match self {
+                        Table::Cached(table) => table,
+                        Table::Extended(table) => table,
+                }
+        }
+}
+
+// Digits per leaf of the divide-and-conquer split. Below two leaves the
+// grouped scalar loop is already as fast as it gets.
+const LEAF_DIGITS: usize = 64;
+const CHECKSUM_LEN: usize = 4;
+// Positions kept in the shared table. The codec is fed untrusted stage-1
+// lines, so the cache must not grow with the input; longer inputs compute
+// the rest of their positions per call and drop them afterwards.
+const MAX_CACHED_POSITIONS: usize = 4096;
+
+impl Clone for MixedRadixCodec {
+        fn clone(&self) -> Self {
+                MixedRadixCodec {
+                        key: self.key.clone(),
+                        bases: self.bases.clone(),
+                        super_alphabet: self.super_alphabet.clone(),
+                        mode: self.mode,
+                        checksum: self.checksum,
+                        positions: RwLock::new(self.positions.read().unwrap().clone()),
+                }
+        }
+}
+
+impl MixedRadixCodec {
+        pub fn new(key: &[u8], bases: &[u32], super_alphabet: &str) -> Result<Self, CodecError> {
+                let symbols: Vec<char> = super_alphabet.chars().collect();
+                if key.is_empty() {
+                        return Err(CodecError::EmptyKey);
+                }
+                if bases.is_empty() {
+                        return Err(CodecError::NoBases);
+                }
+                let mut seen = HashSet::new();
+                for &c in &symbols {
+                        if !seen.insert(c) {
+                                return Err(CodecError::DuplicateSymbol(c));
+                        }
+                }
+                for &base in bases {
+                        if base < 2 {
+                                return Err(CodecError::BaseTooSmall(base));
+                        }
+                        if base as usize > symbols.len() {
+                                return Err(CodecError::BaseTooLarge { base, alphabet_len: symbols.len() });
+                        }
+                }
+                Ok(MixedRadixCodec {
+                        key: key.to_vec(),
+                        bases: bases.to_vec(),
+                        super_alphabet: symbols,
+                        mode: EncodingMode::Legacy,
+                        checksum: false,
+                        positions: RwLock::new(Vec::new()),
+                })
+        }
+
+        pub fn with_mode(mut self, mode: EncodingMode) -> Self {
+                self.mode = mode;
+                self
+        }
+
+        // Base58Check-style: a 4-byte prf32 tag over the payload is appended
+        // before encoding, so a mistyped character shows up as a checksum
+        // mismatch instead of silently decoding to different bytes. Pair it
+        // with `EncodingMode::V1` when payloads may start with 0x00.
+        pub fn with_checksum(mut self, enabled: bool) -> Self {
+                self.checksum = enabled;
+                self
+        }
+
+        fn checksum_of(&self, data: &[u8]) -> [u8; CHECKSUM_LEN] {
+                let mut ctx = b"/check".to_vec();
+                ctx.extend_from_slice(data);
+                prf32(&self.key, &ctx).to_be_bytes()
+        }
+
+        pub fn pos_base(&self, pos_lsb: u32) -> u32 {
+                let mut ctx = b"/base".to_vec();
+                ctx.extend_from_slice(&pos_lsb.to_be_bytes());
+                let hash_val = prf32(&self.key, &ctx);
+                let idx = hash_val % (self.bases.len() as u32);
+                self.bases[idx as usize]
+        }
+
+        pub fn pos_alphabet(&self, pos_lsb: u32, base: u32) -> Vec<char> {
+                let mut ctx = b"/alpha".to_vec();
+                ctx.extend_from_slice(&pos_lsb.to_be_bytes());
+                let seed = prf32(&self.key, &ctx);
+                let mut rng = StdRng::seed_from_u64(seed as u64);
+                let mut bag = self.super_alphabet.clone();
+                bag.shuffle(&mut rng);
+                bag.truncate(base as usize);
+                bag
+        }
+
+        fn position(&self, pos: usize) -> Position {
+                let base = self.pos_base(pos as u32);
+                let alphabet = self.pos_alphabet(pos as u32, base);
+                Position { base, alphabet }
+        }
+
+        // Bases and alphabets only depend on the key and the position, so
+        // the first MAX_CACHED_POSITIONS are computed once and shared by
+        // every later encode/decode.
+        fn positions(&self, n: usize) -> Table<'_> {
+                if n > MAX_CACHED_POSITIONS {
+                        let mut table = self.positions(MAX_CACHED_POSITIONS).to_vec();
+                        table.extend((MAX_CACHED_POSITIONS..n).map(|pos| self.position(pos)));
+                        return Table::Extended(table);
+                }
+                {
+                        let table = self.positions.read().unwrap();
+                        if table.len() >= n {
+                                return Table::Cached(table);
+                        }
+                }
+                {
+                        let mut table = self.positions.write().unwrap();
+                        for pos in table.len()..n {
+                                table.push(self.position(pos));
+                        }
+                }
+                Table::Cached(self.positions.read().unwrap())
+        }
+
+        // Exactly `count` digits of `num` for positions `start..start + count`,
+        // least significant first. Several positions are peeled off per
+        // bignum division by grouping bases whose product fits in a u32.
+        fn small_digits(table: &[Position], mut num: BigUint, start: usize, count: usize, out: &mut Vec<u32>) {
+                let end = start + count;
+                let mut pos = start;
+                while pos < end {
+                        let mut group = 1u64;
+                        let mut stop = pos;
+                        while stop < end && group * table[stop].base as u64 <= u32::MAX as u64 {
+                                group *= table[stop].base as u64;
+                                stop += 1;
+                        }
+                        let mut rem = if num.is_zero() { 0 } else {
+                                let r = (&num % group as u32).to_u32().unwrap_or(0);
+                                num /= group as u32;
+                                r
+                        };
+                        for p in &table[pos..stop] {
+                                out.push(rem % p.base);
+                                rem /= p.base;
+                        }
+                        pos = stop;
+                }
+        }
+
+        // Divide and conquer: split `num` by the product of the lower half of
+        // the positions, convert both halves independently, recurse. Turns
+        // the quadratic digit-by-digit loop into a few large divisions.
+        fn split_digits(table: &[Position], tree: &[Vec<BigUint>], num: BigUint, level: usize, idx: usize, out: &mut Vec<u32>) {
+                if level == 0 {
+                        Self::small_digits(table, num, idx * LEAF_DIGITS, LEAF_DIGITS, out);
+                        return;
+                }
+                let (hi, lo) = num.div_rem(&tree[level - 1][2 * idx]);
+                Self::split_digits(table, tree, lo, level - 1, 2 * idx, out);
+                Self::split_digits(table, tree, hi, level - 1, 2 * idx + 1, out);
+        }
+
+        // Digits, least significant first.
+        fn to_digits(&self, data: &[u8]) -> Vec<u32> {
+                let zeros = match self.mode {
+                        EncodingMode::Legacy => 0,
+                        EncodingMode::V1 => data.iter().take_while(|&&b| b == 0).count(),
+                };
+                let num = BigUint::from_bytes_be(&data[zeros..]);
+                if num.is_zero() && self.mode == EncodingMode::Legacy {
+                        return vec![0];
+                }
+                // Upper bound on the digit count, from the smallest base.
+                let min_base = *self.bases.iter().min().unwrap() as f64;
+                let bound = (num.bits() as f64 / min_base.log2()).ceil() as usize + 1;
+                let mut digits = Vec::with_capacity(bound + zeros);
+                if bound <= LEAF_DIGITS * 2 {
+                        let table = self.positions(bound);
+                        Self::small_digits(&table, num, 0, bound, &mut digits);
+                } else {
+                        let leaves = bound.div_ceil(LEAF_DIGITS).next_power_of_two();
+                        let table = self.positions(leaves * LEAF_DIGITS);
+                        let mut tree: Vec<Vec<BigUint>> = vec![table
+                                .chunks(LEAF_DIGITS)
+                                .take(leaves)
+                                .map(|block| block.iter().fold(BigUint::one(), |acc, p| acc * p.base))
+                                .collect()];
+                        while tree.last().unwrap().len() > 2 {
+                                let next = tree.last().unwrap().chunks(2).map(|pair| &pair[0] * &pair[1]).collect();
+                                tree.push(next);
+                        }
+                        Self::split_digits(&table, &tree, num, tree.len(), 0, &mut digits);
+                }
+                while digits.last() == Some(&0) {
+                        digits.pop();
+                }
+                digits.resize(digits.len() + zeros, 0);
+                if digits.is_empty() && self.mode == EncodingMode::Legacy {
+                        digits.push(0);
+                }
+                digits
+        }
+
+        pub fn encode(&self, data: &[u8]) -> String {
+                if !self.checksum {
+                        return self.encode_payload(data);
+                }
+                let mut payload = data.to_vec();
+                payload.extend_from_slice(&self.checksum_of(data));
+                self.encode_payload(&payload)
+        }
+
+        pub fn decode(&self, encoded: &str) -> Result<Vec<u8>, DecodeError> {
+                let mut payload = self.decode_payload(encoded)?;
+                if !self.checksum {
+                        return Ok(payload);
+                }
+                if payload.len() < CHECKSUM_LEN {
+                        return Err(DecodeError::MissingChecksum);
+                }
+                let tag = payload.split_off(payload.len() - CHECKSUM_LEN);
+                if tag[..] != self.checksum_of(&payload) {
+                        return Err(DecodeError::BadChecksum);
+                }
+                Ok(payload)
+        }
+
+        fn encode_payload(&self, data: &[u8]) -> String {
+                let digits = self.to_digits(data);
+                let n = digits.len();
+                let table = self.positions(n);
+                digits.iter().rev().zip(table[..n].iter().rev())
+                        .map(|(&d, p)| p.alphabet[d as usize])
+                        .collect()
+        }
+
+        fn decode_payload(&self, encoded: &str) -> Result<Vec<u8>, DecodeError> {
+                let chars: Vec<char> = encoded.chars().collect();
+                if chars.is_empty() {
+                        return match self.mode {
+                                EncodingMode::Legacy => Err(DecodeError::Empty),
+                                EncodingMode::V1 => Ok(Vec::new()),
+                        };
+                }
+                let n = chars.len();
+                let table = self.positions(n);
+                let mut num = BigUint::zero();
+                let mut leading_zeros = 0usize;
+                let mut in_prefix = true;
+                for (j, &ch) in chars.iter().enumerate() {
+                        let Position { base, alphabet } = &table[n - 1 - j];
+                        let base = *base;
+                        let val = match alphabet.iter().position(|&c| c == ch) {
+                                Some(v) => v as u32,
+                                None => return Err(DecodeError::InvalidChar { index: j, ch }),
+                        };
+                        in_prefix &= val == 0;
+                        if in_prefix {
+                                leading_zeros += 1;
+                        }
+                        num = num * base + val;
+                }
+                match self.mode {
+                        EncodingMode::Legacy => Ok(num.to_bytes_be()),
+                        EncodingMode::V1 => {
+                                let mut out = vec![0u8; leading_zeros];
+                                if !num.is_zero() {
+                                        out.extend_from_slice(&num.to_bytes_be());
+                                }
+                                Ok(out)
+                        }
+                }
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+        use proptest::prelude::*;
+
+        fn codec(mode: EncodingMode, checksum: bool) -> MixedRadixCodec {
+                DEFAULT_CODEC.clone().with_mode(mode).with_checksum(checksum)
+        }
+
+        // Inputs past ~100 bytes take the divide-and-conquer path; keep the
+        // case count modest, bignum work is slow in debug builds.
+        proptest! {
+                #![proptest_config(ProptestConfig::with_cases(64))]
+
+                #[test]
+                fn v1_round_trips_any_bytes(data in proptest::collection::vec(any::<u8>(), 0..600)) {
+                        let c = codec(EncodingMode::V1, false);
+                        prop_assert_eq!(c.decode(&c.encode(&data)).unwrap(), data);
+                }
+
+                #[test]
+                fn v1_round_trips_zero_prefixed_bytes(zeros in 0usize..8, tail in proptest::collection::vec(any::<u8>(), 0..64)) {
+                        let c = codec(EncodingMode::V1, false);
+                        let mut data = vec![0u8; zeros];
+                        data.extend(tail);
+                        prop_assert_eq!(c.decode(&c.encode(&data)).unwrap(), data);
+                }
+
+                #[test]
+                fn legacy_round_trips_without_leading_zeros(first in 1u8.., rest in proptest::collection::vec(any::<u8>(), 0..600)) {
+                        let c = codec(EncodingMode::Legacy, false);
+                        let mut data = vec![first];
+                        data.extend(rest);
+                        prop_assert_eq!(c.decode(&c.encode(&data)).unwrap(), data);
+                }
+
+                #[test]
+                fn checksummed_round_trips(data in proptest::collection::vec(any::<u8>(), 0..200)) {
+                        let c = codec(EncodingMode::V1, true);
+                        prop_assert_eq!(c.decode(&c.encode(&data)).unwrap(), data);
+                }
+
+                #[test]
+                fn rejects_chars_outside_the_position_alphabet(data in proptest::collection::vec(any::<u8>(), 1..64), pick in any::<prop::sample::Index>()) {
+                        let c = codec(EncodingMode::V1, false);
+                        let mut chars: Vec<char> = c.encode(&data).chars().collect();
+                        let index = pick.index(chars.len());
+                        let table = c.positions(chars.len());
+                        let alphabet = &table[chars.len() - 1 - index].alphabet;
+                        // Base-62 positions use the whole super alphabet; fall back to a
+                        // symbol no position has.
+                        let outsider = c.super_alphabet.iter().copied().find(|ch| !alphabet.contains(ch)).unwrap_or('~');
+                        drop(table);
+                        chars[index] = outsider;
+                        let tampered: String = chars.into_iter().collect();
+                        prop_assert_eq!(c.decode(&tampered), Err(DecodeError::InvalidChar { index, ch: outsider }));
+                }
+        }
+
+        #[test]
+        fn rejects_chars_outside_every_alphabet() {
+                let c = codec(EncodingMode::Legacy, false);
+                let encoded = c.encode(b"rusty");
+                let tampered = format!("{}~", &encoded[..encoded.len() - 1]);
+                assert_eq!(c.decode(&tampered), Err(DecodeError::InvalidChar { index: encoded.chars().count() - 1, ch: '~' }));
+                assert_eq!(c.decode(""), Err(DecodeError::Empty));
+        }
+
+        #[test]
+        fn long_inputs_do_not_grow_the_shared_table() {
+                // 8 KiB is well past MAX_CACHED_POSITIONS digits in any base.
+                let data: Vec<u8> = (0..8192u32).map(|i| (i * 7 + 1) as u8).collect();
+                let encoded = DEFAULT_CODEC.encode(&data);
+                assert!(encoded.chars().count() > MAX_CACHED_POSITIONS);
+                assert_eq!(DEFAULT_CODEC.decode(&encoded).unwrap(), data);
+                assert!(DEFAULT_CODEC.positions.read().unwrap().len() <= MAX_CACHED_POSITIONS);
+        }
+
+        #[test]
+        fn legacy_drops_leading_zero_bytes() {
+                let c = codec(EncodingMode::Legacy, false);
+                assert_eq!(c.decode(&c.encode(&[0, 0, 7])).unwrap(), vec![7]);
+        }
+}
@@ -1,3 +1,11 @@
 pub mod verify;
 pub mod session;
 pub mod secret_provider;
+pub mod config;
+pub mod scoreboard;
+pub mod pow;
+pub mod roster;
+pub mod proxy_proto;
+pub mod acl;
+pub mod mixed_radix;
+pub mod selftest;
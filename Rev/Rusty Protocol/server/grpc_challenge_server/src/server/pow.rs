@@ -0,0 +1,136 @@
+use once_cell::sync::Lazy;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::config::CONFIG;
+
+// Prefixes handed out and not yet answered. A prefix is removed on its first
+// check, so a solved nonce can never be replayed on another connection.
+static ISSUED: Lazy<Mutex<HashMap<String, Instant>>> =
+        Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn is_enabled() -> bool {
+        CONFIG.pow_difficulty > 0
+}
+
+pub fn difficulty() -> u32 {
+        CONFIG.pow_difficulty.min(64)
+}
+
+fn expire_stale(map: &mut HashMap<String, Instant>) {
+        let ttl = Duration::from_secs(CONFIG.pow_timeout_secs.max(1));
+        map.retain(|_, issued| issued.elapsed() < ttl);
+}
+
+pub fn new_challenge() -> String {
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill(&mut bytes);
+        let prefix = hex::encode(bytes);
+
+        let mut map = ISSUED.lock().unwrap();
+        expire_stale(&mut map);
+        map.insert(prefix.clone(), Instant::now());
+        prefix
+}
+
+pub fn prompt(prefix: &str) -> String {
+        format!(
+                "Proof of work: find a nonce so that sha256(\"{}\" + nonce) starts with {} zero bits.\nnonce: ",
+                prefix,
+                difficulty(),
+        )
+}
+
+fn leading_zero_bits(digest: &[u8]) -> u32 {
+        let mut bits = 0;
+        for &b in digest {
+                if b == 0 {
+                        bits += 8;
+                } else {
+                        bits += b.leading_zeros();
+                        break;
+                }
+        }
+        bits
+}
+
+pub fn check(prefix: &str, nonce: &str) -> bool {
+        check_bits(prefix, nonce, difficulty())
+}
+
+fn check_bits(prefix: &str, nonce: &str, bits: u32) -> bool {
+        let issued = {
+                let mut map = ISSUED.lock().unwrap();
+                expire_stale(&mut map);
+                map.remove(prefix).is_some()
+        };
+        if !issued || nonce.is_empty() || nonce.len() > 64 {
+                return false;
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(prefix.as_bytes());
+        hasher.update(nonce.as_bytes());
+        leading_zero_bits(&hasher.finalize()) >= bits
+}
+
+pub fn forget(prefix: &str) {
+        ISSUED.lock().unwrap().remove(prefix);
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        fn zero_bits(prefix: &str, nonce: &str) -> u32 {
+                leading_zero_bits(&Sha256::digest(format!("{}{}", prefix, nonce)))
+        }
+
+        fn find_nonce(prefix: &str, accept: impl Fn(u32) -> bool) -> String {
+                (0u64..).map(|n| n.to_string()).find(|n| accept(zero_bits(prefix, n))).unwrap()
+        }
+
+        #[test]
+        fn counts_leading_zero_bits() {
+                assert_eq!(leading_zero_bits(&[0xff]), 0);
+                assert_eq!(leading_zero_bits(&[0x00, 0x00, 0x10, 0x00]), 19);
+                assert_eq!(leading_zero_bits(&[0x00, 0x01]), 15);
+                assert_eq!(leading_zero_bits(&[0x00; 4]), 32);
+        }
+
+        #[test]
+        fn enforces_difficulty() {
+                let prefix = new_challenge();
+                let weak = find_nonce(&prefix, |z| z < 10);
+                assert!(!check_bits(&prefix, &weak, 10));
+
+                let prefix = new_challenge();
+                let strong = find_nonce(&prefix, |z| z >= 10);
+                assert!(check_bits(&prefix, &strong, 10));
+        }
+
+        #[test]
+        fn rejects_replays_and_unissued_prefixes() {
+                let prefix = new_challenge();
+                let nonce = find_nonce(&prefix, |z| z >= 8);
+                assert!(check_bits(&prefix, &nonce, 8));
+                assert!(!check_bits(&prefix, &nonce, 8), "a solved prefix must not be accepted twice");
+
+                let unissued = "00".repeat(16);
+                assert!(!check_bits(&unissued, &find_nonce(&unissued, |z| z >= 8), 8));
+
+                let prefix = new_challenge();
+                forget(&prefix);
+                assert!(!check_bits(&prefix, &find_nonce(&prefix, |z| z >= 8), 8));
+        }
+
+        #[test]
+        fn rejects_empty_and_oversized_nonces() {
+                let prefix = new_challenge();
+                assert!(!check_bits(&prefix, "", 0));
+                let prefix = new_challenge();
+                assert!(!check_bits(&prefix, &"0".repeat(65), 0));
+        }
+}
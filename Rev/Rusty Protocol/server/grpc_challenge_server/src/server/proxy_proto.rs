@@ -0,0 +1,282 @@
+use ipnet::IpNet;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::{timeout, Duration};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::server::{Connected, TcpConnectInfo};
+
+use super::config::CONFIG;
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+const V1_MAX_LEN: usize = 107;
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn bad(msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// Works out who is really on the other end. Only connections coming from a
+// trusted balancer must (and may) carry a PROXY header; for everyone else
+// the socket peer is used as-is, so players cannot spoof their address.
+pub async fn client_addr(stream: &mut TcpStream) -> io::Result<SocketAddr> {
+        let peer = stream.peer_addr()?;
+        let trusted: &[IpNet] = if CONFIG.proxy_protocol { &CONFIG.proxy_trusted } else { &[] };
+        resolve_peer(stream, peer, trusted).await
+}
+
+async fn resolve_peer<R: AsyncRead + Unpin>(stream: &mut R, peer: SocketAddr, trusted: &[IpNet]) -> io::Result<SocketAddr> {
+        if !trusted.iter().any(|net| net.contains(&peer.ip())) {
+                return Ok(peer);
+        }
+        let parsed = timeout(HEADER_TIMEOUT, read_header(stream))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "PROXY header timeout"))??;
+        Ok(parsed.unwrap_or(peer))
+}
+
+// Returns None for v1 `UNKNOWN` and v2 `LOCAL` headers (health checks).
+async fn read_header<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+        let mut head = [0u8; 12];
+        stream.read_exact(&mut head).await?;
+        if &head == V2_SIGNATURE {
+                read_v2(stream).await
+        } else if head.starts_with(b"PROXY ") {
+                read_v1(stream, &head).await
+        } else {
+                Err(bad("missing PROXY header"))
+        }
+}
+
+async fn read_v1<R: AsyncRead + Unpin>(stream: &mut R, head: &[u8]) -> io::Result<Option<SocketAddr>> {
+        let mut line = head.to_vec();
+        while !line.ends_with(b"\r\n") {
+                if line.len() >= V1_MAX_LEN {
+                        return Err(bad("PROXY v1 header too long"));
+                }
+                line.push(stream.read_u8().await?);
+        }
+        let text = std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| bad("PROXY v1 not ASCII"))?;
+        let parts: Vec<&str> = text.split(' ').collect();
+        match parts.as_slice() {
+                ["PROXY", "UNKNOWN", ..] => Ok(None),
+                ["PROXY", proto, src, _dst, sport, _dport] if *proto == "TCP4" || *proto == "TCP6" => {
+                        let ip: IpAddr = src.parse().map_err(|_| bad("PROXY v1 bad source address"))?;
+                        if ip.is_ipv4() != (*proto == "TCP4") {
+                                return Err(bad("PROXY v1 address family mismatch"));
+                        }
+                        let port: u16 = sport.parse().map_err(|_| bad("PROXY v1 bad source port"))?;
+                        Ok(Some(SocketAddr::new(ip, port)))
+                }
+                _ => Err(bad("malformed PROXY v1 header")),
+        }
+}
+
+async fn read_v2<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+        let mut hdr = [0u8; 4];
+        stream.read_exact(&mut hdr).await?;
+        let (ver_cmd, family) = (hdr[0], hdr[1]);
+        let len = u16::from_be_bytes([hdr[2], hdr[3]]) as usize;
+        if ver_cmd >> 4 != 2 {
+                return Err(bad("unsupported PROXY v2 version"));
+        }
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await?;
+        match ver_cmd & 0x0f {
+                0x0 => return Ok(None),
+                0x1 => {}
+                _ => return Err(bad("unsupported PROXY v2 command")),
+        }
+        match family {
+                0x11 if len >= 12 => {
+                        let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+                        let port = u16::from_be_bytes([body[8], body[9]]);
+                        Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+                }
+                0x21 if len >= 36 => {
+                        let mut octets = [0u8; 16];
+                        octets.copy_from_slice(&body[0..16]);
+                        let port = u16::from_be_bytes([body[32], body[33]]);
+                        Ok(Some(SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port)))
+                }
+                0x11 | 0x21 => Err(bad("PROXY v2 address block too short")),
+                _ => Ok(None),
+        }
+}
+
+// TCP stream that reports the PROXY-derived address to tonic, so
+// `Request::remote_addr()` inside the service sees the real player.
+pub struct ProxiedStream {
+        inner: TcpStream,
+        remote: SocketAddr,
+}
+
+impl ProxiedStream {
+        pub fn new(inner: TcpStream, remote: SocketAddr) -> Self {
+                ProxiedStream { inner, remote }
+        }
+}
+
+impl Connected for ProxiedStream {
+        type ConnectInfo = TcpConnectInfo;
+
+        fn connect_info(&self) -> Self::ConnectInfo {
+                TcpConnectInfo {
+                        local_addr: self.inner.local_addr().ok(),
+                        remote_addr: Some(self.remote),
+                }
+        }
+}
+
+impl AsyncRead for ProxiedStream {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+}
+
+impl AsyncWrite for ProxiedStream {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.inner).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+}
+
+// Accept loop for the gRPC listener. Headers are read in per-connection
+// tasks so a stalled client cannot hold up everyone else's accept.
+pub fn incoming(listener: TcpListener) -> ReceiverStream<io::Result<ProxiedStream>> {
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(async move {
+                loop {
+                        let (mut stream, _) = match listener.accept().await {
+                                Ok(conn) => conn,
+                                Err(e) => {
+                                        eprintln!("gRPC accept error: {}", e);
+                                        continue;
+                                }
+                        };
+                        let tx = tx.clone();
+                        tokio::spawn(async move {
+                                match client_addr(&mut stream).await {
+                                        Ok(remote) => {
+                                                let _ = tx.send(Ok(ProxiedStream::new(stream, remote))).await;
+                                        }
+                                        Err(e) => eprintln!("[-] gRPC connection dropped: {}", e),
+                                }
+                        });
+                }
+        });
+        ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        const BALANCER: &str = "10.0.0.2:40000";
+
+        fn trusted() -> Vec<IpNet> {
+                vec!["10.0.0.0/8".parse().unwrap()]
+        }
+
+        async fn resolve(peer: &str, bytes: &[u8]) -> io::Result<SocketAddr> {
+                let mut stream = bytes;
+                resolve_peer(&mut stream, peer.parse().unwrap(), &trusted()).await
+        }
+
+        fn v2(ver_cmd: u8, family: u8, body: &[u8]) -> Vec<u8> {
+                let mut h = V2_SIGNATURE.to_vec();
+                h.extend_from_slice(&[ver_cmd, family]);
+                h.extend_from_slice(&(body.len() as u16).to_be_bytes());
+                h.extend_from_slice(body);
+                h
+        }
+
+        fn addr(s: &str) -> SocketAddr {
+                s.parse().unwrap()
+        }
+
+        #[tokio::test]
+        async fn parses_v1_lines() {
+                let got = resolve(BALANCER, b"PROXY TCP4 198.51.100.7 10.0.0.1 51234 5001\r\nhello").await.unwrap();
+                assert_eq!(got, addr("198.51.100.7:51234"));
+                let got = resolve(BALANCER, b"PROXY TCP6 2001:db8::7 2001:db8::1 4242 5001\r\n").await.unwrap();
+                assert_eq!(got, addr("[2001:db8::7]:4242"));
+                let got = resolve(BALANCER, b"PROXY UNKNOWN\r\n").await.unwrap();
+                assert_eq!(got, addr(BALANCER));
+        }
+
+        #[tokio::test]
+        async fn rejects_bad_v1_lines() {
+                for line in [
+                        &b"PROXY TCP4 2001:db8::7 10.0.0.1 1 2\r\n"[..],
+                        b"PROXY TCP4 198.51.100.7 10.0.0.1 99999 5001\r\n",
+                        b"PROXY TCP4 198.51.100.7 10.0.0.1\r\n",
+                        b"PROXY UDP4 198.51.100.7 10.0.0.1 1 2\r\n",
+                ] {
+                        let err = resolve(BALANCER, line).await.unwrap_err();
+                        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", String::from_utf8_lossy(line));
+                }
+                let mut long = b"PROXY TCP4 ".to_vec();
+                long.extend([b'1'; 200]);
+                assert_eq!(resolve(BALANCER, &long).await.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+
+        #[tokio::test]
+        async fn parses_v2_headers() {
+                let mut body = vec![198, 51, 100, 7, 10, 0, 0, 1];
+                body.extend_from_slice(&51234u16.to_be_bytes());
+                body.extend_from_slice(&5001u16.to_be_bytes());
+                assert_eq!(resolve(BALANCER, &v2(0x21, 0x11, &body)).await.unwrap(), addr("198.51.100.7:51234"));
+
+                let src: Ipv6Addr = "2001:db8::7".parse().unwrap();
+                let mut body = src.octets().to_vec();
+                body.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+                body.extend_from_slice(&4242u16.to_be_bytes());
+                body.extend_from_slice(&5001u16.to_be_bytes());
+                assert_eq!(resolve(BALANCER, &v2(0x21, 0x21, &body)).await.unwrap(), addr("[2001:db8::7]:4242"));
+
+                // LOCAL (health check) keeps the balancer's own address.
+                assert_eq!(resolve(BALANCER, &v2(0x20, 0x00, &[])).await.unwrap(), addr(BALANCER));
+        }
+
+        #[tokio::test]
+        async fn rejects_truncated_and_unsupported_headers() {
+                let full = v2(0x21, 0x11, &[198, 51, 100, 7, 10, 0, 0, 1, 0, 1, 0, 2]);
+                for cut in [5, 13, 15, full.len() - 1] {
+                        let err = resolve(BALANCER, &full[..cut]).await.unwrap_err();
+                        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "cut at {}", cut);
+                }
+                let err = resolve(BALANCER, b"PROXY TCP4 198.51.100.7").await.unwrap_err();
+                assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+                assert_eq!(resolve(BALANCER, &v2(0x21, 0x11, &full[16..24])).await.unwrap_err().kind(), io::ErrorKind::InvalidData);
+                assert_eq!(resolve(BALANCER, &v2(0x21, 0x21, &[0; 12])).await.unwrap_err().kind(), io::ErrorKind::InvalidData);
+                assert_eq!(resolve(BALANCER, &v2(0x11, 0x11, &[0; 12])).await.unwrap_err().kind(), io::ErrorKind::InvalidData);
+                assert_eq!(resolve(BALANCER, &v2(0x22, 0x11, &[0; 12])).await.unwrap_err().kind(), io::ErrorKind::InvalidData);
+                assert_eq!(resolve(BALANCER, b"GET / HTTP/1.1\r\n\r\n").await.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+
+        #[tokio::test]
+        async fn ignores_headers_from_untrusted_peers() {
+                let header = b"PROXY TCP4 198.51.100.7 10.0.0.1 51234 5001\r\n";
+                let mut stream = &header[..];
+                let got = resolve_peer(&mut stream, addr("203.0.113.9:1234"), &trusted()).await.unwrap();
+                assert_eq!(got, addr("203.0.113.9:1234"));
+                assert_eq!(stream, &header[..], "an untrusted peer's bytes must be left for the protocol handler");
+
+                // Proxy support switched off: nobody is trusted.
+                let mut stream = &header[..];
+                assert_eq!(resolve_peer(&mut stream, addr(BALANCER), &[]).await.unwrap(), addr(BALANCER));
+        }
+}
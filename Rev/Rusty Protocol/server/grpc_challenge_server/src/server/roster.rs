@@ -0,0 +1,201 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::RwLock;
+
+pub const MAX_ID_LEN: usize = 64;
+
+// Left unset when no roster file is configured, in which case any
+// well-formed id is admitted.
+static ROSTER: Lazy<RwLock<Option<Roster>>> = Lazy::new(|| RwLock::new(None));
+
+// challenge_id (the id or token issued to a team) -> team.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Roster {
+        teams: HashMap<String, Team>,
+}
+
+// `ctfd_id` is the team's numeric id on a CTFd scoreboard, when known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Team {
+        pub name: String,
+        pub ctfd_id: Option<u64>,
+}
+
+// Why an id was turned away: malformed ids are the caller's mistake,
+// well-formed ids missing from the roster are not allowed in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdmitError {
+        Invalid(&'static str),
+        NotRostered,
+}
+
+impl AdmitError {
+        pub fn reason(self) -> &'static str {
+                match self {
+                        AdmitError::Invalid(reason) => reason,
+                        AdmitError::NotRostered => "unknown challenge id, use the id issued to your team",
+                }
+        }
+}
+
+impl Roster {
+        // One team per line: `team name,challenge_id[,ctfd_team_id]`, where a
+        // third column counts only if it is all digits (team names may contain
+        // commas). Blank lines and `#` comments are skipped. Ids go through the
+        // same validation as player input.
+        pub fn parse(data: &str) -> Result<Self, String> {
+                let mut teams = HashMap::new();
+                for (lineno, raw) in data.lines().enumerate() {
+                        let line = raw.trim();
+                        if line.is_empty() || line.starts_with('#') {
+                                continue;
+                        }
+                        let (mut team, mut id) = match line.rsplit_once(',') {
+                                Some((t, i)) => (t.trim(), i.trim()),
+                                None => return Err(format!("roster line {}: expected `team,id`", lineno + 1)),
+                        };
+                        let mut ctfd_id = None;
+                        if let Some((t, i)) = team.rsplit_once(',') {
+                                if !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()) {
+                                        ctfd_id = Some(id.parse().map_err(|_| format!("roster line {}: CTFd team id out of range", lineno + 1))?);
+                                        (team, id) = (t.trim(), i.trim());
+                                }
+                        }
+                        if team.is_empty() {
+                                return Err(format!("roster line {}: empty team name", lineno + 1));
+                        }
+                        validate_id(id).map_err(|e| format!("roster line {}: {}", lineno + 1, e))?;
+                        if teams.insert(id.to_string(), Team { name: team.to_string(), ctfd_id }).is_some() {
+                                return Err(format!("roster line {}: duplicate id {}", lineno + 1, id));
+                        }
+                }
+                Ok(Roster { teams })
+        }
+
+        pub fn len(&self) -> usize {
+                self.teams.len()
+        }
+
+        pub fn team(&self, id: &str) -> Option<&Team> {
+                self.teams.get(id)
+        }
+}
+
+// Called at startup and again on SIGHUP; a file that fails to parse leaves
+// the current roster in place.
+pub fn load(path: &str) -> Result<usize, String> {
+        let data = fs::read_to_string(path)
+                .map_err(|e| format!("cannot read roster {}: {}", path, e))?;
+        let roster = Roster::parse(&data)?;
+        let count = roster.len();
+        *ROSTER.write().unwrap() = Some(roster);
+        Ok(count)
+}
+
+pub fn validate_id(id: &str) -> Result<(), &'static str> {
+        if id.is_empty() {
+                return Err("id must not be empty");
+        }
+        if id.len() > MAX_ID_LEN {
+                return Err("id is too long");
+        }
+        if !id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-') {
+                return Err("id may only contain letters, digits, '_' and '-'");
+        }
+        Ok(())
+}
+
+pub fn team_for(id: &str) -> Option<Team> {
+        ROSTER.read().unwrap().as_ref().and_then(|r| r.team(id).cloned())
+}
+
+// Gate used by both listeners before any session state is created.
+pub fn admit(id: &str) -> Result<(), AdmitError> {
+        check(ROSTER.read().unwrap().as_ref(), id)
+}
+
+fn check(roster: Option<&Roster>, id: &str) -> Result<(), AdmitError> {
+        validate_id(id).map_err(AdmitError::Invalid)?;
+        match roster {
+                Some(r) if r.team(id).is_none() => Err(AdmitError::NotRostered),
+                _ => Ok(()),
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        const ROSTER_FILE: &str = "\
+# team name,challenge_id
+Null Pointers,team-01
+
+Rust Belt, Inc.,tok_9f3a1c77e2
+  # indented comment
+Byte Me , team_03 
+";
+
+        #[test]
+        fn validates_id_length_and_charset() {
+                assert_eq!(validate_id(""), Err("id must not be empty"));
+                assert_eq!(validate_id(&"a".repeat(MAX_ID_LEN)), Ok(()));
+                assert_eq!(validate_id(&"a".repeat(MAX_ID_LEN + 1)), Err("id is too long"));
+                for bad in ["team 01", "team/01", "tëam", "id;drop", "a\nb"] {
+                        assert!(validate_id(bad).is_err(), "{:?} should be rejected", bad);
+                }
+                assert_eq!(validate_id("Team_01-b"), Ok(()));
+        }
+
+        #[test]
+        fn parses_names_ids_and_tokens_skipping_comments() {
+                let roster = Roster::parse(ROSTER_FILE).unwrap();
+                assert_eq!(roster.len(), 3);
+                let name = |id| roster.team(id).map(|t| t.name.as_str());
+                assert_eq!(name("team-01"), Some("Null Pointers"));
+                assert_eq!(name("tok_9f3a1c77e2"), Some("Rust Belt, Inc."));
+                assert_eq!(name("team_03"), Some("Byte Me"));
+                assert_eq!(name("Null Pointers"), None);
+                assert!(roster.teams.values().all(|t| t.ctfd_id.is_none()));
+        }
+
+        #[test]
+        fn numeric_third_column_is_the_ctfd_team_id() {
+                let roster = Roster::parse("Null Pointers,team-01,12\nRust Belt, Inc.,tok_9f3a,7\nSolo,42").unwrap();
+                let team = |id| roster.team(id).cloned().unwrap();
+                assert_eq!(team("team-01"), Team { name: "Null Pointers".to_string(), ctfd_id: Some(12) });
+                assert_eq!(team("tok_9f3a"), Team { name: "Rust Belt, Inc.".to_string(), ctfd_id: Some(7) });
+                // Two columns: a numeric value is the challenge id itself.
+                assert_eq!(team("42"), Team { name: "Solo".to_string(), ctfd_id: None });
+                assert!(Roster::parse("Team,team-01,99999999999999999999").is_err());
+        }
+
+        #[test]
+        fn rejects_malformed_and_duplicate_lines() {
+                assert_eq!(Roster::parse("ok,team-01\nno separator\n"), Err("roster line 2: expected `team,id`".to_string()));
+                assert_eq!(Roster::parse(",team-01"), Err("roster line 1: empty team name".to_string()));
+                assert_eq!(Roster::parse("Team,bad id"), Err("roster line 1: id may only contain letters, digits, '_' and '-'".to_string()));
+                assert_eq!(Roster::parse("A,team-01\n\nB,team-01"), Err("roster line 3: duplicate id team-01".to_string()));
+        }
+
+        // A roster that cannot be read is reported, never swapped in.
+        #[test]
+        fn missing_file_is_an_error() {
+                let err = load("/nonexistent/roster.csv").unwrap_err();
+                assert!(err.starts_with("cannot read roster /nonexistent/roster.csv"), "{}", err);
+        }
+
+        #[test]
+        fn admits_any_valid_id_without_a_roster() {
+                assert_eq!(check(None, "anything-goes"), Ok(()));
+                assert_eq!(check(None, "not valid"), Err(AdmitError::Invalid("id may only contain letters, digits, '_' and '-'")));
+        }
+
+        #[test]
+        fn admits_only_rostered_ids_with_a_roster() {
+                let roster = Roster::parse(ROSTER_FILE).unwrap();
+                assert_eq!(check(Some(&roster), "team-01"), Ok(()));
+                assert_eq!(check(Some(&roster), "team-99"), Err(AdmitError::NotRostered));
+                assert_eq!(check(Some(&roster), ""), Err(AdmitError::Invalid("id must not be empty")));
+        }
+}
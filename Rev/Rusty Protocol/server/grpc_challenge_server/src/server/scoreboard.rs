@@ -0,0 +1,449 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Duration};
+
+use super::config::{ScoreboardMode, CONFIG};
+use super::roster::{self, Team};
+
+// Serializes writes and the batch hand-off on the outbox file. Never held
+// across network I/O, so a slow scoreboard cannot stall solving players.
+static OUTBOX_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+// Keeps the startup flush and the periodic flusher from replaying the same
+// batch twice. Appends do not wait on it.
+static FLUSH_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+static HTTP: Lazy<reqwest::Client> = Lazy::new(|| {
+        reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("http client")
+});
+
+// A solve as it is queued in the outbox. In webhook mode it is also the
+// body POSTed as JSON to SCOREBOARD_URL:
+//
+//     {"team": "..", "challenge": "..", "challenge_id": "..", "solved_at": <unix secs>,
+//      "team_id": <CTFd team id, if the roster has one>}
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolveEvent {
+        pub team: String,
+        pub challenge: String,
+        pub challenge_id: String,
+        pub solved_at: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub team_id: Option<u64>,
+}
+
+// In CTFd mode SCOREBOARD_URL is the admin submissions endpoint
+// (`/api/v1/submissions`) and SCOREBOARD_TOKEN an admin API token. A
+// submission of type "correct" records the solve for the team; CTFd must
+// run in teams mode, with each team's id in the roster's third column.
+#[derive(Debug, Serialize)]
+struct CtfdSubmission<'a> {
+        challenge_id: u64,
+        team_id: u64,
+        provided: &'a str,
+        #[serde(rename = "type")]
+        kind: &'static str,
+}
+
+// Where and how solve events are delivered. The server builds one from
+// CONFIG; the tests point one at a local listener.
+#[derive(Clone, Debug)]
+pub struct Notifier {
+        pub url: String,
+        pub token: Option<String>,
+        pub mode: ScoreboardMode,
+        pub retries: u32,
+        pub backoff: Duration,
+        pub outbox: String,
+}
+
+static NOTIFIER: Lazy<Option<Notifier>> = Lazy::new(|| {
+        CONFIG.scoreboard_url.clone().map(|url| Notifier {
+                url,
+                token: CONFIG.scoreboard_token.clone(),
+                mode: CONFIG.scoreboard_mode,
+                retries: CONFIG.scoreboard_retries,
+                backoff: Duration::from_secs(1),
+                outbox: CONFIG.scoreboard_outbox.clone(),
+        })
+});
+
+// Without a roster entry the challenge_id stands in for the team name.
+fn solve_event(challenge_id: &str, team: Option<Team>, challenge: &str, solved_at: u64) -> SolveEvent {
+        SolveEvent {
+                team: team.as_ref().map_or_else(|| challenge_id.to_string(), |t| t.name.clone()),
+                challenge: challenge.to_string(),
+                challenge_id: challenge_id.to_string(),
+                solved_at,
+                team_id: team.and_then(|t| t.ctfd_id),
+        }
+}
+
+// Fire-and-forget: the player already got CONGRATULATIONS, the scoreboard
+// catches up in the background (or later from the outbox).
+pub fn notify_solve(challenge_id: &str) {
+        let notifier = match NOTIFIER.as_ref() {
+                Some(n) => n,
+                None => return,
+        };
+        let solved_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+        let event = solve_event(challenge_id, roster::team_for(challenge_id), &CONFIG.scoreboard_challenge, solved_at);
+        tokio::spawn(notifier.report(event));
+}
+
+// Replays the outbox once at startup and then every SCOREBOARD_FLUSH_SECS,
+// so a scoreboard that comes back mid-event is caught up without a restart.
+pub async fn run_outbox_flusher() {
+        let notifier = match NOTIFIER.as_ref() {
+                Some(n) => n,
+                None => return,
+        };
+        let period = Duration::from_secs(CONFIG.scoreboard_flush_secs.max(1));
+        loop {
+                notifier.flush_outbox().await;
+                sleep(period).await;
+        }
+}
+
+impl Notifier {
+        // CTFd can only be told about teams it knows the id of; such events
+        // are logged and dropped instead of being retried forever.
+        fn deliverable(&self, event: &SolveEvent) -> bool {
+                match (self.mode, event.team_id) {
+                        (ScoreboardMode::Ctfd { .. }, None) => {
+                                eprintln!("[scoreboard] no CTFd team id in the roster for {}, not reporting", event.challenge_id);
+                                false
+                        }
+                        _ => true,
+                }
+        }
+
+        async fn post_event(&self, event: &SolveEvent) -> Result<(), String> {
+                let mut req = match (self.mode, event.team_id) {
+                        (ScoreboardMode::Webhook, _) => HTTP.post(&self.url).json(event),
+                        (ScoreboardMode::Ctfd { challenge_id }, Some(team_id)) => HTTP.post(&self.url).json(&CtfdSubmission {
+                                challenge_id,
+                                team_id,
+                                provided: &event.challenge_id,
+                                kind: "correct",
+                        }),
+                        (ScoreboardMode::Ctfd { .. }, None) => return Err("no CTFd team id".to_string()),
+                };
+                if let Some(token) = &self.token {
+                        req = req.header("Authorization", format!("Token {}", token));
+                }
+                let resp = req.send().await.map_err(|e| e.to_string())?;
+                if resp.status().is_success() {
+                        Ok(())
+                } else {
+                        Err(format!("HTTP {}", resp.status()))
+                }
+        }
+
+        async fn deliver_with_retry(&self, event: &SolveEvent) -> Result<(), String> {
+                let attempts = self.retries.max(1);
+                let mut last_err = String::new();
+                for attempt in 0..attempts {
+                        match self.post_event(event).await {
+                                Ok(()) => {
+                                        println!("[scoreboard] solve reported for {}", event.team);
+                                        return Ok(());
+                                }
+                                Err(e) => last_err = e,
+                        }
+                        if attempt + 1 < attempts {
+                                sleep(self.backoff * (1 << attempt.min(5))).await;
+                        }
+                }
+                Err(last_err)
+        }
+
+        async fn report(&self, event: SolveEvent) {
+                if !self.deliverable(&event) {
+                        return;
+                }
+                if let Err(e) = self.deliver_with_retry(&event).await {
+                        eprintln!("[scoreboard] giving up on {}: {}", event.team, e);
+                        if let Err(e) = self.append_outbox(&event).await {
+                                eprintln!("[scoreboard] outbox write failed: {}", e);
+                        }
+                }
+        }
+
+        async fn append_outbox(&self, event: &SolveEvent) -> std::io::Result<()> {
+                let mut line = serde_json::to_string(event)?;
+                line.push('\n');
+                let _guard = OUTBOX_LOCK.lock().await;
+                let mut f = OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&self.outbox)
+                        .await?;
+                f.write_all(line.as_bytes()).await?;
+                f.flush().await
+        }
+
+        // Re-sends everything left over from earlier failures. The current
+        // outbox is moved aside under the lock and replayed without it; events
+        // that still fail go back through append_outbox. A batch interrupted
+        // by a crash stays in the side file and is replayed on the next flush.
+        pub async fn flush_outbox(&self) {
+                let _flushing = FLUSH_LOCK.lock().await;
+                let batch_path = format!("{}.flushing", self.outbox);
+                {
+                        let _guard = OUTBOX_LOCK.lock().await;
+                        if fs::metadata(&batch_path).await.is_err() {
+                                match fs::rename(&self.outbox, &batch_path).await {
+                                        Ok(()) => {}
+                                        Err(e) if e.kind() == ErrorKind::NotFound => return,
+                                        Err(e) => {
+                                                eprintln!("[scoreboard] cannot take outbox batch: {}", e);
+                                                return;
+                                        }
+                                }
+                        }
+                }
+                let data = match fs::read_to_string(&batch_path).await {
+                        Ok(d) => d,
+                        Err(e) => {
+                                eprintln!("[scoreboard] cannot read outbox batch: {}", e);
+                                return;
+                        }
+                };
+                let mut sent = 0usize;
+                for line in data.lines().filter(|l| !l.trim().is_empty()) {
+                        let event: SolveEvent = match serde_json::from_str(line) {
+                                Ok(ev) => ev,
+                                Err(_) => {
+                                        eprintln!("[scoreboard] dropping malformed outbox entry");
+                                        continue;
+                                }
+                        };
+                        if !self.deliverable(&event) {
+                                continue;
+                        }
+                        match self.deliver_with_retry(&event).await {
+                                Ok(()) => sent += 1,
+                                Err(_) => {
+                                        if let Err(e) = self.append_outbox(&event).await {
+                                                eprintln!("[scoreboard] outbox write failed: {}", e);
+                                        }
+                                }
+                        }
+                }
+                if let Err(e) = fs::remove_file(&batch_path).await {
+                        eprintln!("[scoreboard] cannot remove outbox batch: {}", e);
+                }
+                if sent > 0 {
+                        println!("[scoreboard] flushed {} queued solve(s)", sent);
+                }
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+        use std::sync::Arc;
+        use tokio::io::AsyncReadExt;
+        use tokio::net::TcpListener;
+
+        struct Received {
+                head: String,
+                body: Vec<u8>,
+        }
+
+        impl Received {
+                fn header(&self, name: &str) -> Option<&str> {
+                        self.head.lines().skip(1).find_map(|l| {
+                                let (k, v) = l.split_once(':')?;
+                                k.trim().eq_ignore_ascii_case(name).then(|| v.trim())
+                        })
+                }
+        }
+
+        // Minimal HTTP/1.1 endpoint: answers each request with the next status
+        // in `statuses` (the last one repeats) and records what it was sent.
+        struct MockScoreboard {
+                url: String,
+                received: Arc<std::sync::Mutex<Vec<Received>>>,
+                statuses: Arc<std::sync::Mutex<Vec<u16>>>,
+        }
+
+        impl MockScoreboard {
+                async fn start(statuses: &[u16]) -> Self {
+                        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+                        let url = format!("http://{}/solves", listener.local_addr().unwrap());
+                        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+                        let statuses = Arc::new(std::sync::Mutex::new(statuses.to_vec()));
+                        let (rec, st) = (received.clone(), statuses.clone());
+                        tokio::spawn(async move {
+                                while let Ok((mut sock, _)) = listener.accept().await {
+                                        let request = read_request(&mut sock).await;
+                                        let status = {
+                                                let mut st = st.lock().unwrap();
+                                                if st.len() > 1 { st.remove(0) } else { st[0] }
+                                        };
+                                        rec.lock().unwrap().push(request);
+                                        let resp = format!("HTTP/1.1 {} X\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                                        let _ = sock.write_all(resp.as_bytes()).await;
+                                }
+                        });
+                        MockScoreboard { url, received, statuses }
+                }
+
+                fn set_status(&self, status: u16) {
+                        *self.statuses.lock().unwrap() = vec![status];
+                }
+
+                fn events(&self) -> Vec<SolveEvent> {
+                        self.received.lock().unwrap().iter().map(|r| serde_json::from_slice(&r.body).unwrap()).collect()
+                }
+        }
+
+        async fn read_request(sock: &mut tokio::net::TcpStream) -> Received {
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 1024];
+                let head_end = loop {
+                        let n = sock.read(&mut chunk).await.unwrap();
+                        assert!(n > 0, "client closed before sending headers");
+                        buf.extend_from_slice(&chunk[..n]);
+                        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                                break i + 4;
+                        }
+                };
+                let head = String::from_utf8(buf[..head_end].to_vec()).unwrap();
+                let mut received = Received { head, body: buf[head_end..].to_vec() };
+                let len: usize = received.header("content-length").map_or(0, |v| v.parse().unwrap());
+                while received.body.len() < len {
+                        let n = sock.read(&mut chunk).await.unwrap();
+                        assert!(n > 0, "client closed mid-body");
+                        received.body.extend_from_slice(&chunk[..n]);
+                }
+                received
+        }
+
+        fn notifier(url: &str, retries: u32, outbox_name: &str) -> Notifier {
+                notifier_with_mode(url, retries, outbox_name, ScoreboardMode::Webhook)
+        }
+
+        fn notifier_with_mode(url: &str, retries: u32, outbox_name: &str, mode: ScoreboardMode) -> Notifier {
+                let outbox = std::env::temp_dir()
+                        .join(format!("scoreboard-{}-{}.jsonl", std::process::id(), outbox_name))
+                        .to_string_lossy()
+                        .into_owned();
+                let _ = std::fs::remove_file(&outbox);
+                Notifier {
+                        url: url.to_string(),
+                        token: Some("s3cret".to_string()),
+                        mode,
+                        retries,
+                        backoff: Duration::from_millis(1),
+                        outbox,
+                }
+        }
+
+        fn event(team: &str) -> SolveEvent {
+                SolveEvent {
+                        team: team.to_string(),
+                        challenge: "Rusty Protocol".to_string(),
+                        challenge_id: format!("{}-id", team),
+                        solved_at: 1_700_000_000,
+                        team_id: None,
+                }
+        }
+
+        #[tokio::test]
+        async fn posts_json_body_with_token_header() {
+                let mock = MockScoreboard::start(&[200]).await;
+                let n = notifier(&mock.url, 3, "body");
+                n.deliver_with_retry(&event("alpha")).await.unwrap();
+
+                let received = mock.received.lock().unwrap();
+                assert_eq!(received.len(), 1);
+                assert!(received[0].head.starts_with("POST /solves HTTP/1.1\r\n"));
+                assert_eq!(received[0].header("authorization"), Some("Token s3cret"));
+                assert_eq!(received[0].header("content-type"), Some("application/json"));
+                let body: serde_json::Value = serde_json::from_slice(&received[0].body).unwrap();
+                assert_eq!(body, serde_json::json!({
+                        "team": "alpha",
+                        "challenge": "Rusty Protocol",
+                        "challenge_id": "alpha-id",
+                        "solved_at": 1_700_000_000u64,
+                }));
+        }
+
+        #[tokio::test]
+        async fn retries_on_server_errors() {
+                let mock = MockScoreboard::start(&[503, 502, 200]).await;
+                let n = notifier(&mock.url, 3, "retry");
+                n.deliver_with_retry(&event("bravo")).await.unwrap();
+                assert_eq!(mock.events(), vec![event("bravo"); 3]);
+
+                let mock = MockScoreboard::start(&[500]).await;
+                let n = notifier(&mock.url, 2, "retry-exhausted");
+                assert_eq!(n.deliver_with_retry(&event("bravo")).await, Err("HTTP 500 Internal Server Error".to_string()));
+                assert_eq!(mock.events().len(), 2);
+        }
+
+        #[tokio::test]
+        async fn failed_events_are_queued_and_replayed() {
+                let mock = MockScoreboard::start(&[500]).await;
+                let n = notifier(&mock.url, 2, "outbox");
+                n.report(event("charlie")).await;
+                n.report(event("delta")).await;
+                let queued = std::fs::read_to_string(&n.outbox).unwrap();
+                let queued: Vec<SolveEvent> = queued.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+                assert_eq!(queued, vec![event("charlie"), event("delta")]);
+
+                // Still down: the batch goes back to the outbox untouched.
+                n.flush_outbox().await;
+                assert_eq!(std::fs::read_to_string(&n.outbox).unwrap().lines().count(), 2);
+
+                mock.set_status(200);
+                mock.received.lock().unwrap().clear();
+                n.flush_outbox().await;
+                assert_eq!(mock.events(), vec![event("charlie"), event("delta")]);
+                assert!(std::fs::metadata(&n.outbox).is_err(), "outbox should be drained");
+                assert!(std::fs::metadata(format!("{}.flushing", n.outbox)).is_err());
+        }
+
+        #[tokio::test]
+        async fn ctfd_mode_posts_a_correct_submission_for_the_rostered_team() {
+                let roster = roster::Roster::parse("Null Pointers,team-01,12\nNo Scoreboard,team-02").unwrap();
+                let mock = MockScoreboard::start(&[200]).await;
+                let n = notifier_with_mode(&mock.url, 1, "ctfd", ScoreboardMode::Ctfd { challenge_id: 7 });
+
+                let solved = solve_event("team-01", roster.team("team-01").cloned(), "Rusty Protocol", 1_700_000_000);
+                assert_eq!((solved.team.as_str(), solved.team_id), ("Null Pointers", Some(12)));
+                n.report(solved).await;
+                {
+                        let received = mock.received.lock().unwrap();
+                        assert_eq!(received.len(), 1);
+                        assert!(received[0].head.starts_with("POST /solves HTTP/1.1\r\n"));
+                        assert_eq!(received[0].header("authorization"), Some("Token s3cret"));
+                        let body: serde_json::Value = serde_json::from_slice(&received[0].body).unwrap();
+                        assert_eq!(body, serde_json::json!({
+                                "challenge_id": 7,
+                                "team_id": 12,
+                                "provided": "team-01",
+                                "type": "correct",
+                        }));
+                }
+
+                // A team without a CTFd id is neither sent nor queued.
+                n.report(solve_event("team-02", roster.team("team-02").cloned(), "Rusty Protocol", 1_700_000_000)).await;
+                assert_eq!(mock.received.lock().unwrap().len(), 1);
+                assert!(std::fs::metadata(&n.outbox).is_err());
+        }
+}
@@ -19,3 +19,25 @@ pub fn get_endpoint_and_key_hint(_id: &str) -> String {
                                     CHACHA_KEY,
                                         )
 }
+
+// The JSON players must recover from TARGET_CIPHER. build.rs encodes this
+// with the event codec, so changing the credentials above regenerates the
+// target instead of silently making stage 1 unsolvable.
+pub fn stage1_answer() -> String {
+        format!("{{\"Token\": {}, \"Key\": {}}}", json_quote(GRPC_TOKEN), json_quote(GRPC_KEY))
+}
+
+fn json_quote(s: &str) -> String {
+        let mut out = String::with_capacity(s.len() + 2);
+        out.push('"');
+        for c in s.chars() {
+                match c {
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                        c => out.push(c),
+                }
+        }
+        out.push('"');
+        out
+}
@@ -0,0 +1,60 @@
+use crate::secret::FlagRequest;
+use super::secret_provider::{CHAM_KEY, FINAL_PLAINTEXT, GRPC_KEY, GRPC_TOKEN, ULTIMATE_PLAINTEXT};
+use super::session;
+use super::verify::{self, chacha_key, cham_key, stage_cipher, FlagSvc, CHACHA_STAGE, CHAM_STAGE};
+
+const SELF_TEST_ID: &str = "__self_test__";
+
+// Plays the whole challenge in-process with the intended answers, the same
+// way handle_connection and FlagSvc drive a real player. Any stage that
+// can't be passed means the deployed constants don't fit together.
+pub async fn run() -> Result<(), String> {
+        stage_cipher::check_stage(&CHACHA_STAGE, chacha_key())?;
+        stage_cipher::check_stage(&CHAM_STAGE, cham_key())?;
+        println!("[self-test] stage ciphers accept the configured keys");
+
+        verify::check_target_cipher().map_err(|e| format!("stage 1: {}", e))?;
+        println!("[self-test] stage 1 (mixed-radix answer) ok");
+
+        if FINAL_PLAINTEXT.as_bytes() != CHAM_KEY {
+                return Err("stage 3: FINAL_PLAINTEXT is not the CHAM key players are told to use".to_string());
+        }
+
+        session::init_challenge(SELF_TEST_ID);
+        let result = drive_session().await;
+        session::clear_challenge(SELF_TEST_ID);
+        result
+}
+
+async fn drive_session() -> Result<(), String> {
+        let req = FlagRequest {
+                token: GRPC_TOKEN.to_string(),
+                key: GRPC_KEY.to_string(),
+                challenge_id: SELF_TEST_ID.to_string(),
+        };
+        // The self-test id is never on a roster, so it skips that check
+        // explicitly instead of depending on running before roster::load.
+        let reply = FlagSvc
+                .issue(req, false)
+                .await
+                .map_err(|s| format!("stage 2: Flag rejected the configured credentials: {}", s.message()))?;
+        if !session::is_auth_success(SELF_TEST_ID) {
+                return Err("stage 2: Flag did not mark the session as authenticated".to_string());
+        }
+        println!("[self-test] stage 2 (gRPC Flag) ok");
+
+        let (chacha_ok, chacha_hex) = verify::verify_chacha_input(SELF_TEST_ID, FINAL_PLAINTEXT);
+        if !chacha_ok || chacha_hex != reply.message {
+                return Err("stage 3: FINAL_PLAINTEXT does not reproduce the ChaCha ciphertext".to_string());
+        }
+        println!("[self-test] stage 3 (ChaCha) ok");
+
+        verify::generate_and_get_cham_cipher(SELF_TEST_ID, &reply.session)
+                .ok_or("stage 4: CHAM ciphertext could not be generated")?;
+        let (cham_ok, _) = verify::verify_cham_input(SELF_TEST_ID, ULTIMATE_PLAINTEXT, &reply.session);
+        if !cham_ok {
+                return Err("stage 4: ULTIMATE_PLAINTEXT does not reproduce the CHAM ciphertext".to_string());
+        }
+        println!("[self-test] stage 4 (CHAM) ok");
+        Ok(())
+}
@@ -0,0 +1,175 @@
+use std::convert::TryInto;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+use super::chacha_logic::{chacha20_block, encrypt_variant, ChaChaVariant};
+use super::error::CipherError;
+
+// RFC 8439 section 2.8 AEAD, parameterised over the block function: with
+// `Standard` it is plain ChaCha20-Poly1305, with `Custom` the one-time key
+// and keystream come from the challenge block instead.
+
+const MASK26: u32 = 0x3ff_ffff;
+
+fn le32(b: &[u8]) -> u32 {
+        u32::from_le_bytes(b[..4].try_into().unwrap())
+}
+
+#[no_mangle] #[inline(never)]
+pub fn poly1305_mac(key: &[u8; 32], msg: &[u8]) -> [u8; 16] {
+        let r0 = le32(&key[0..]) & 0x3ff_ffff;
+        let r1 = (le32(&key[3..]) >> 2) & 0x3ff_ff03;
+        let r2 = (le32(&key[6..]) >> 4) & 0x3ff_c0ff;
+        let r3 = (le32(&key[9..]) >> 6) & 0x3f0_3fff;
+        let r4 = (le32(&key[12..]) >> 8) & 0x00f_ffff;
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+        let (mut h0, mut h1, mut h2, mut h3, mut h4) = (0u32, 0u32, 0u32, 0u32, 0u32);
+
+        for chunk in msg.chunks(16) {
+                let mut m = [0u8; 17];
+                m[..chunk.len()].copy_from_slice(chunk);
+                m[chunk.len()] = 1;
+                let hibit = if chunk.len() == 16 { 1 << 24 } else { 0 };
+                h0 += le32(&m[0..]) & MASK26;
+                h1 += (le32(&m[3..]) >> 2) & MASK26;
+                h2 += (le32(&m[6..]) >> 4) & MASK26;
+                h3 += (le32(&m[9..]) >> 6) & MASK26;
+                h4 += (le32(&m[12..]) >> 8) | hibit;
+
+                let mul = |a: u32, b: u32| a as u64 * b as u64;
+                let d0 = mul(h0, r0) + mul(h1, s4) + mul(h2, s3) + mul(h3, s2) + mul(h4, s1);
+                let mut d1 = mul(h0, r1) + mul(h1, r0) + mul(h2, s4) + mul(h3, s3) + mul(h4, s2);
+                let mut d2 = mul(h0, r2) + mul(h1, r1) + mul(h2, r0) + mul(h3, s4) + mul(h4, s3);
+                let mut d3 = mul(h0, r3) + mul(h1, r2) + mul(h2, r1) + mul(h3, r0) + mul(h4, s4);
+                let mut d4 = mul(h0, r4) + mul(h1, r3) + mul(h2, r2) + mul(h3, r1) + mul(h4, r0);
+
+                h0 = d0 as u32 & MASK26; d1 += d0 >> 26;
+                h1 = d1 as u32 & MASK26; d2 += d1 >> 26;
+                h2 = d2 as u32 & MASK26; d3 += d2 >> 26;
+                h3 = d3 as u32 & MASK26; d4 += d3 >> 26;
+                h4 = d4 as u32 & MASK26;
+                h0 += (d4 >> 26) as u32 * 5;
+                h1 += h0 >> 26; h0 &= MASK26;
+        }
+
+        // Fully carry h, then subtract p = 2^130 - 5 if h >= p.
+        let mut c;
+        c = h1 >> 26; h1 &= MASK26; h2 += c;
+        c = h2 >> 26; h2 &= MASK26; h3 += c;
+        c = h3 >> 26; h3 &= MASK26; h4 += c;
+        c = h4 >> 26; h4 &= MASK26; h0 += c * 5;
+        c = h0 >> 26; h0 &= MASK26; h1 += c;
+
+        let mut g0 = h0.wrapping_add(5); c = g0 >> 26; g0 &= MASK26;
+        let mut g1 = h1.wrapping_add(c); c = g1 >> 26; g1 &= MASK26;
+        let mut g2 = h2.wrapping_add(c); c = g2 >> 26; g2 &= MASK26;
+        let mut g3 = h3.wrapping_add(c); c = g3 >> 26; g3 &= MASK26;
+        let g4 = h4.wrapping_add(c).wrapping_sub(1 << 26);
+        let keep_g = (g4 >> 31).wrapping_sub(1);
+        h0 = (h0 & !keep_g) | (g0 & keep_g);
+        h1 = (h1 & !keep_g) | (g1 & keep_g);
+        h2 = (h2 & !keep_g) | (g2 & keep_g);
+        h3 = (h3 & !keep_g) | (g3 & keep_g);
+        h4 = (h4 & !keep_g) | (g4 & keep_g);
+
+        let words = [
+                h0 | (h1 << 26),
+                (h1 >> 6) | (h2 << 20),
+                (h2 >> 12) | (h3 << 14),
+                (h3 >> 18) | (h4 << 8),
+        ];
+        let mut tag = [0u8; 16];
+        let mut carry = 0u64;
+        for i in 0..4 {
+                let f = words[i] as u64 + le32(&key[16 + 4 * i..]) as u64 + carry;
+                tag[4 * i..4 * i + 4].copy_from_slice(&(f as u32).to_le_bytes());
+                carry = f >> 32;
+        }
+        tag
+}
+
+#[no_mangle] #[inline(never)]
+pub fn poly1305_key_gen(variant: ChaChaVariant, key: &[u8], nonce: &[u8]) -> Result<Zeroizing<[u8; 32]>, CipherError> {
+        let block = Zeroizing::new(chacha20_block(variant, key, 0, nonce)?);
+        Ok(Zeroizing::new(block[..32].try_into().unwrap()))
+}
+
+fn aead_mac(otk: &[u8; 32], aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
+        let pad = |n: usize| (16 - n % 16) % 16;
+        let mut data = Vec::with_capacity(aad.len() + ciphertext.len() + 48);
+        data.extend_from_slice(aad);
+        data.resize(data.len() + pad(aad.len()), 0);
+        data.extend_from_slice(ciphertext);
+        data.resize(data.len() + pad(ciphertext.len()), 0);
+        data.extend_from_slice(&(aad.len() as u64).to_le_bytes());
+        data.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+        poly1305_mac(otk, &data)
+}
+
+#[no_mangle] #[inline(never)]
+pub fn aead_seal(variant: ChaChaVariant, key: &[u8], nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, [u8; 16]), CipherError> {
+        let otk = poly1305_key_gen(variant, key, nonce)?;
+        let ciphertext = encrypt_variant(variant, key, nonce, 1, plaintext)?;
+        let tag = aead_mac(&otk, aad, &ciphertext);
+        Ok((ciphertext, tag))
+}
+
+#[no_mangle] #[inline(never)]
+pub fn aead_open(variant: ChaChaVariant, key: &[u8], nonce: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>, CipherError> {
+        let otk = poly1305_key_gen(variant, key, nonce)?;
+        let expected = aead_mac(&otk, aad, ciphertext);
+        if !bool::from(expected[..].ct_eq(tag)) {
+                return Err(CipherError::TagMismatch);
+        }
+        encrypt_variant(variant, key, nonce, 1, ciphertext)
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        // RFC 8439 section 2.5.2 (Poly1305) and 2.8.2 (AEAD) vectors.
+        const POLY_KEY: &str = "85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b";
+        const POLY_MSG: &[u8] = b"Cryptographic Forum Research Group";
+        const POLY_TAG: &str = "a8061dc1305136c6c22b8baf0c0127a9";
+        const AEAD_KEY: &str = "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f";
+        const AEAD_NONCE: &str = "070000004041424344454647";
+        const AEAD_AAD: &str = "50515253c0c1c2c3c4c5c6c7";
+        const AEAD_PLAINTEXT: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        const AEAD_CIPHERTEXT: &str = "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d63dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b3692ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc3ff4def08e4b7a9de576d26586cec64b6116";
+        const AEAD_TAG: &str = "1ae10b594f09e26a7e902ecbd0600691";
+
+        fn inputs() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+                (hex::decode(AEAD_KEY).unwrap(), hex::decode(AEAD_NONCE).unwrap(), hex::decode(AEAD_AAD).unwrap())
+        }
+
+        #[test]
+        fn poly1305_matches_rfc8439() {
+                let poly_key: [u8; 32] = hex::decode(POLY_KEY).unwrap().try_into().unwrap();
+                assert_eq!(hex::encode(poly1305_mac(&poly_key, POLY_MSG)), POLY_TAG);
+        }
+
+        #[test]
+        fn standard_aead_matches_rfc8439() {
+                let (key, nonce, aad) = inputs();
+                let (ct, tag) = aead_seal(ChaChaVariant::Standard, &key, &nonce, &aad, AEAD_PLAINTEXT).unwrap();
+                assert_eq!(hex::encode(&ct), AEAD_CIPHERTEXT);
+                assert_eq!(hex::encode(tag), AEAD_TAG);
+                assert_eq!(aead_open(ChaChaVariant::Standard, &key, &nonce, &aad, &ct, &tag).unwrap(), AEAD_PLAINTEXT);
+        }
+
+        // The custom variant has no external reference, so it is only checked
+        // for a round trip and for rejecting tampered input.
+        #[test]
+        fn custom_aead_round_trips_and_rejects_tampering() {
+                let (key, nonce, aad) = inputs();
+                let (mut ct, mut tag) = aead_seal(ChaChaVariant::Custom, &key, &nonce, &aad, AEAD_PLAINTEXT).unwrap();
+                assert_eq!(aead_open(ChaChaVariant::Custom, &key, &nonce, &aad, &ct, &tag).unwrap(), AEAD_PLAINTEXT);
+                assert_eq!(aead_open(ChaChaVariant::Custom, &key, &nonce, &aad[1..], &ct, &tag), Err(CipherError::TagMismatch));
+                ct[0] ^= 1;
+                assert_eq!(aead_open(ChaChaVariant::Custom, &key, &nonce, &aad, &ct, &tag), Err(CipherError::TagMismatch));
+                ct[0] ^= 1;
+                tag[15] ^= 0x80;
+                assert_eq!(aead_open(ChaChaVariant::Custom, &key, &nonce, &aad, &ct, &tag), Err(CipherError::TagMismatch));
+        }
+}
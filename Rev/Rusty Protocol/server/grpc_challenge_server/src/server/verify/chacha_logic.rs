@@ -0,0 +1,387 @@
+use cipher::consts::{U12, U32};
+use cipher::inout::InOutBuf;
+use cipher::{
+        Iv, IvSizeUser, Key, KeyIvInit, KeySizeUser, OverflowError, SeekNum, StreamCipher,
+        StreamCipherError, StreamCipherSeek,
+};
+use serde::Serialize;
+use std::convert::TryInto;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use super::error::{check_key, check_nonce, CipherError};
+use super::stage_cipher::StageCipher;
+
+#[no_mangle] pub static CONST: &[u8] = b"expand 32-byte k";
+#[no_mangle] pub static INITIAL_COUNTER: u32 = 1;
+
+// `Standard` is RFC 8439 ChaCha20. `Custom` is the challenge tweak: an extra
+// `round_custom` permutation after every double round.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChaChaVariant {
+        Standard = 0,
+        Custom = 1,
+}
+
+#[no_mangle] #[inline(never)]
+pub fn to_le_u32_words(b: &[u8]) -> Result<Vec<u32>, CipherError> {
+        if !b.len().is_multiple_of(4) { return Err(CipherError::Unaligned { len: b.len() }); }
+        Ok(b.chunks_exact(4)
+                .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+                .collect())
+}
+#[no_mangle] #[inline(never)]
+pub fn words_to_le_bytes(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|&w| w.to_le_bytes()).collect()
+}
+#[no_mangle] #[inline(never)]
+pub fn quarter_round(a: u32, b: u32, c: u32, d: u32) -> (u32, u32, u32, u32) {
+        let mut a = a; let mut b = b; let mut c = c; let mut d = d;
+        a = a.wrapping_add(b); d ^= a; d = d.rotate_left(16);
+        c = c.wrapping_add(d); b ^= c; b = b.rotate_left(12);
+        a = a.wrapping_add(b); d ^= a; d = d.rotate_left(8);
+        c = c.wrapping_add(d); b ^= c; b = b.rotate_left(7);
+        (a, b, c, d)
+}
+#[no_mangle] #[inline(never)]
+pub extern "C" fn qr_idx(st: &mut [u32; 16], i: usize, j: usize, k: usize, l: usize) {
+        let (a, b, c, d) = quarter_round(st[i], st[j], st[k], st[l]);
+        st[i] = a; st[j] = b; st[k] = c; st[l] = d;
+}
+#[no_mangle] #[inline(never)]
+pub extern "C" fn round_column(st: &mut [u32; 16]) {
+        for s in 0..4 { qr_idx(st, s, s + 4, s + 8, s + 12); }
+}
+#[no_mangle] #[inline(never)]
+pub extern "C" fn round_diagonal(st: &mut [u32; 16]) {
+        for i in 0..4 {
+                let a = i; let b = ((i + 1) & 3) + 4;
+                let c = ((i + 2) & 3) + 8; let d = ((i + 3) & 3) + 12;
+                qr_idx(st, a, b, c, d);
+        }
+}
+#[no_mangle] #[inline(never)]
+pub extern "C" fn round_custom(st: &mut [u32; 16]) {
+        qr_idx(st, 6, 3, 1, 0); qr_idx(st, 10, 7, 4, 2);
+        qr_idx(st, 13, 11, 8, 5); qr_idx(st, 15, 14, 12, 9);
+}
+#[no_mangle] #[inline(never)]
+pub fn build_state(key: &[u8], counter: u32, nonce: &[u8]) -> Result<[u32; 16], CipherError> {
+        check_key(key, 32)?;
+        check_nonce(nonce, 12)?;
+        let const_words = to_le_u32_words(CONST)?;
+        let key_words = to_le_u32_words(key)?;
+        let nonce_words = to_le_u32_words(nonce)?;
+        let mut state = [0u32; 16]; let mut i = 0;
+        for w in const_words { state[i] = w; i += 1; }
+        for w in key_words { state[i] = w; i += 1; }
+        state[i] = counter; i += 1;
+        for w in nonce_words { state[i] = w; i += 1; }
+        Ok(state)
+}
+// The 10 double rounds. `hook` sees the state after every round function
+// with the double-round index and the round's name; the untraced callers
+// pass a no-op that compiles away.
+#[inline(always)]
+fn permute(variant: ChaChaVariant, st: &mut [u32; 16], mut hook: impl FnMut(usize, &'static str, &[u32; 16])) {
+        for n in 0..10 {
+                round_column(st); hook(n, "column", st);
+                round_diagonal(st); hook(n, "diagonal", st);
+                if variant == ChaChaVariant::Custom { round_custom(st); hook(n, "custom", st); }
+        }
+}
+#[no_mangle] #[inline(never)]
+pub fn chacha20_block(variant: ChaChaVariant, key: &[u8], counter: u32, nonce: &[u8]) -> Result<Vec<u8>, CipherError> {
+        let mut x = build_state(key, counter, nonce)?; let mut st = x;
+        permute(variant, &mut st, |_, _, _| {});
+        for i in 0..16 { st[i] = st[i].wrapping_add(x[i]); }
+        let out = words_to_le_bytes(&st);
+        x.zeroize(); st.zeroize();
+        Ok(out)
+}
+#[no_mangle] #[inline(never)]
+pub fn custom_chacha20_block(key: &[u8], counter: u32, nonce: &[u8]) -> Result<Vec<u8>, CipherError> {
+        chacha20_block(ChaChaVariant::Custom, key, counter, nonce)
+}
+
+fn hex_words(st: &[u32]) -> Vec<String> {
+        st.iter().map(|w| format!("{:08x}", w)).collect()
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ChaChaTraceStep {
+        pub double_round: usize,
+        pub round: &'static str,
+        pub state: Vec<String>,
+}
+
+// Every intermediate state of one block, as 16 hex words in state order,
+// for comparing a player's implementation round by round.
+#[derive(Clone, Debug, Serialize)]
+pub struct ChaChaTrace {
+        pub variant: String,
+        pub counter: u32,
+        pub nonce: String,
+        pub initial: Vec<String>,
+        pub steps: Vec<ChaChaTraceStep>,
+        pub output: String,
+}
+
+impl ChaChaTrace {
+        pub fn to_json(&self) -> String {
+                serde_json::to_string_pretty(self).expect("trace serializes")
+        }
+}
+
+// Traced form of `chacha20_block`; `output` is the same keystream block.
+pub fn chacha20_block_trace(variant: ChaChaVariant, key: &[u8], counter: u32, nonce: &[u8]) -> Result<ChaChaTrace, CipherError> {
+        let x = build_state(key, counter, nonce)?; let mut st = x;
+        let mut steps = Vec::new();
+        permute(variant, &mut st, |double_round, round, st| {
+                steps.push(ChaChaTraceStep { double_round, round, state: hex_words(st) });
+        });
+        for i in 0..16 { st[i] = st[i].wrapping_add(x[i]); }
+        Ok(ChaChaTrace {
+                variant: format!("{:?}", variant).to_lowercase(),
+                counter,
+                nonce: hex::encode(nonce),
+                initial: hex_words(&x),
+                steps,
+                output: hex::encode(words_to_le_bytes(&st)),
+        })
+}
+#[no_mangle] #[inline(never)]
+pub fn keystream_variant(variant: ChaChaVariant, key: &[u8], nonce: &[u8], counter: u32, nbytes: usize) -> Result<Vec<u8>, CipherError> {
+        let mut out = vec![0u8; nbytes];
+        ChaChaCipher::from_slices(variant, key, nonce, counter)?.apply_keystream(&mut out);
+        Ok(out)
+}
+#[no_mangle] #[inline(never)]
+pub fn keystream(key: &[u8], nonce: &[u8], counter: u32, nbytes: usize) -> Result<Vec<u8>, CipherError> {
+        keystream_variant(ChaChaVariant::Custom, key, nonce, counter, nbytes)
+}
+#[no_mangle] #[inline(never)]
+pub fn encrypt_variant(variant: ChaChaVariant, key: &[u8], nonce: &[u8], counter: u32, plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+        let mut out = plaintext.to_vec();
+        ChaChaCipher::from_slices(variant, key, nonce, counter)?.apply_keystream(&mut out);
+        Ok(out)
+}
+#[no_mangle] #[inline(never)]
+pub fn encrypt(key: &[u8], nonce: &[u8], counter: u32, plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+        encrypt_variant(ChaChaVariant::Custom, key, nonce, counter, plaintext)
+}
+
+// Stage 3: the custom variant from INITIAL_COUNTER under the 12-byte
+// session nonce.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChaChaStage;
+
+impl StageCipher for ChaChaStage {
+        fn key_len(&self) -> usize { 32 }
+        fn nonce_len(&self) -> usize { 12 }
+        fn encrypt(&self, key: &[u8], nonce: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+                encrypt(key, nonce, INITIAL_COUNTER, plaintext)
+        }
+        fn decrypt(&self, key: &[u8], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, CipherError> {
+                self.encrypt(key, nonce, ciphertext)
+        }
+        fn description(&self) -> &'static str {
+                "stage 3 custom ChaCha20"
+        }
+}
+
+// Streaming form of `encrypt_variant`: the input state and one keystream
+// block are kept between calls, so XORing in place never allocates. The
+// block counter wraps like `keystream` does rather than reporting the end of
+// the keystream. Positions for `StreamCipherSeek` count from block 0, so a
+// fresh cipher at INITIAL_COUNTER reports position 64. The key words and
+// the buffered keystream are wiped on drop.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct ChaChaCipher {
+        #[zeroize(skip)]
+        variant: ChaChaVariant,
+        input: [u32; 16],
+        counter: u32,
+        block: [u8; 64],
+        pos: usize,
+}
+
+impl ChaChaCipher {
+        pub fn new(variant: ChaChaVariant, key: &[u8; 32], nonce: &[u8; 12], counter: u32) -> Self {
+                let mut input = [0u32; 16];
+                let words = CONST.chunks_exact(4).chain(key.chunks_exact(4));
+                for (slot, w) in input.iter_mut().zip(words) {
+                        *slot = u32::from_le_bytes(w.try_into().unwrap());
+                }
+                for (slot, w) in input[13..].iter_mut().zip(nonce.chunks_exact(4)) {
+                        *slot = u32::from_le_bytes(w.try_into().unwrap());
+                }
+                ChaChaCipher { variant, input, counter, block: [0; 64], pos: 0 }
+        }
+
+        pub fn from_slices(variant: ChaChaVariant, key: &[u8], nonce: &[u8], counter: u32) -> Result<Self, CipherError> {
+                check_key(key, 32)?;
+                check_nonce(nonce, 12)?;
+                Ok(Self::new(variant, key.try_into().unwrap(), nonce.try_into().unwrap(), counter))
+        }
+
+        pub fn seek(&mut self, counter: u32) {
+                self.counter = counter;
+                self.pos = 0;
+        }
+
+        fn refill(&mut self) {
+                let mut x = self.input;
+                x[12] = self.counter;
+                let mut st = x;
+                permute(self.variant, &mut st, |_, _, _| {});
+                for (out, (s, x)) in self.block.chunks_exact_mut(4).zip(st.iter().zip(x.iter())) {
+                        out.copy_from_slice(&s.wrapping_add(*x).to_le_bytes());
+                }
+                x.zeroize(); st.zeroize();
+                self.counter = self.counter.wrapping_add(1);
+        }
+
+        // Up to `max` bytes of unused keystream, generating a block if none is buffered.
+        fn next_keystream(&mut self, max: usize) -> &[u8] {
+                if self.pos == 0 {
+                        self.refill();
+                }
+                let start = self.pos;
+                let n = max.min(64 - start);
+                self.pos = (start + n) % 64;
+                &self.block[start..start + n]
+        }
+
+        pub fn apply_keystream(&mut self, mut data: &mut [u8]) {
+                while !data.is_empty() {
+                        let ks = self.next_keystream(data.len());
+                        let (head, tail) = data.split_at_mut(ks.len());
+                        head.iter_mut().zip(ks).for_each(|(d, k)| *d ^= k);
+                        data = tail;
+                }
+        }
+}
+
+impl KeySizeUser for ChaChaCipher {
+        type KeySize = U32;
+}
+
+impl IvSizeUser for ChaChaCipher {
+        type IvSize = U12;
+}
+
+// RustCrypto construction: the challenge variant starting at INITIAL_COUNTER.
+impl KeyIvInit for ChaChaCipher {
+        fn new(key: &Key<Self>, iv: &Iv<Self>) -> Self {
+                ChaChaCipher::new(ChaChaVariant::Custom, key.as_ref(), iv.as_ref(), INITIAL_COUNTER)
+        }
+}
+
+impl StreamCipher for ChaChaCipher {
+        fn try_apply_keystream_inout(&mut self, mut buf: InOutBuf<'_, '_, u8>) -> Result<(), StreamCipherError> {
+                while !buf.is_empty() {
+                        let ks = self.next_keystream(buf.len());
+                        let (mut head, tail) = buf.split_at(ks.len());
+                        head.xor_in2out(ks);
+                        buf = tail;
+                }
+                Ok(())
+        }
+}
+
+impl StreamCipherSeek for ChaChaCipher {
+        fn try_current_pos<T: SeekNum>(&self) -> Result<T, OverflowError> {
+                T::from_block_byte(self.counter, self.pos as u8, 64)
+        }
+
+        fn try_seek<T: SeekNum>(&mut self, pos: T) -> Result<(), StreamCipherError> {
+                let (counter, byte): (u32, u8) = pos.into_block_byte(64).map_err(|_| StreamCipherError)?;
+                self.seek(counter);
+                if byte != 0 {
+                        self.refill();
+                        self.pos = byte as usize;
+                }
+                Ok(())
+        }
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        // RFC 8439 section 2.3.2 / 2.4.2 inputs, reused for the custom vectors
+        // (pinned from solve/custom_chacha20.py). A failing standard vector
+        // means the shared machinery is broken; if only the custom vectors
+        // fail, the tweak itself changed.
+        const KEY: [u8; 32] = [
+                0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+                0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
+        ];
+        const BLOCK_NONCE: &str = "000000090000004a00000000";
+        const ENCRYPT_NONCE: &str = "000000000000004a00000000";
+        const PLAINTEXT: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+        const RFC8439_BLOCK: &str = "10f1e7e4d13b5915500fdd1fa32071c4c7d1f4c733c068030422aa9ac3d46c4ed2826446079faa0914c2d705d98b02a2b5129cd1de164eb9cbd083e8a2503c4e";
+        const RFC8439_CIPHERTEXT: &str = "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0bf91b65c5524733ab8f593dabcd62b3571639d624e65152ab8f530c359f0861d807ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab77937365af90bbf74a35be6b40b8eedf2785e42874d";
+        const CUSTOM_BLOCK: &str = "ea2e1ca1c477140748f97820cd3a7d906e69fb637ff183afdc43f2191e596e653034720414e7aac2bbfe8568a3cde450cd8d1b8272a99d9f37f3dd9f5ff93637";
+        const CUSTOM_CIPHERTEXT: &str = "682b72531fb4f916ebb67ef45bbe2f1fc580b97e5204c302ab05f95d0d2cf88dc3170a94ff981221c0fdaf1b1497698681371f631149910a1fe1833aed55109343cb124b47cb3cdcbbb5ba7994cc3c24924b60e98b6e066e22eb1c172dd539ac31491415a1b67ce8da080d30f878e1d5e40d";
+
+        fn block_hex(variant: ChaChaVariant) -> String {
+                hex::encode(chacha20_block(variant, &KEY, 1, &hex::decode(BLOCK_NONCE).unwrap()).unwrap())
+        }
+
+        fn encrypt_hex(variant: ChaChaVariant) -> String {
+                hex::encode(encrypt_variant(variant, &KEY, &hex::decode(ENCRYPT_NONCE).unwrap(), 1, PLAINTEXT).unwrap())
+        }
+
+        #[test]
+        fn standard_block_matches_rfc8439() {
+                assert_eq!(block_hex(ChaChaVariant::Standard), RFC8439_BLOCK);
+        }
+
+        #[test]
+        fn standard_encryption_matches_rfc8439() {
+                assert_eq!(encrypt_hex(ChaChaVariant::Standard), RFC8439_CIPHERTEXT);
+        }
+
+        #[test]
+        fn custom_block_matches_pinned_vector() {
+                assert_eq!(block_hex(ChaChaVariant::Custom), CUSTOM_BLOCK);
+        }
+
+        #[test]
+        fn custom_encryption_matches_pinned_vector() {
+                assert_eq!(encrypt_hex(ChaChaVariant::Custom), CUSTOM_CIPHERTEXT);
+        }
+
+        #[test]
+        fn trace_matches_untraced_block() {
+                let trace = chacha20_block_trace(ChaChaVariant::Custom, &KEY, 1, &hex::decode(BLOCK_NONCE).unwrap()).unwrap();
+                assert_eq!(trace.output, CUSTOM_BLOCK);
+                assert_eq!(trace.steps.len(), 30);
+        }
+
+        // The streaming cipher must agree with the one-shot path however the
+        // input is split, and seeking back must replay the same keystream.
+        #[test]
+        fn streaming_matches_one_shot_and_seeks() {
+                let nonce: [u8; 12] = hex::decode(ENCRYPT_NONCE).unwrap().try_into().unwrap();
+                let mut buf = PLAINTEXT.to_vec();
+                let mut cipher = <ChaChaCipher as KeyIvInit>::new(&KEY.into(), &nonce.into());
+                let mut rest = &mut buf[..];
+                for n in [1, 7, 64, 3, 40] {
+                        let (head, tail) = rest.split_at_mut(n.min(rest.len()));
+                        cipher.apply_keystream(head);
+                        rest = tail;
+                }
+                StreamCipher::apply_keystream(&mut cipher, rest);
+                assert_eq!(hex::encode(&buf), CUSTOM_CIPHERTEXT);
+
+                StreamCipherSeek::seek(&mut cipher, 64u32 + 70);
+                let mut tail = buf[70..].to_vec();
+                cipher.apply_keystream(&mut tail);
+                assert_eq!(tail, PLAINTEXT[70..]);
+                assert_eq!(cipher.current_pos::<u64>(), 64 + PLAINTEXT.len() as u64);
+        }
+}
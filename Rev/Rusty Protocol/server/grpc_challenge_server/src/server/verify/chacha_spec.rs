@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::chacha_logic::{to_le_u32_words, words_to_le_bytes, ChaChaVariant};
+use super::error::{check_key, check_nonce, CipherError};
+
+pub const MAX_DOUBLE_ROUNDS: u32 = 64;
+
+// Describes a ChaCha-like block function so new reversing variants can be
+// generated from data instead of hand-editing `round_custom`. Each double
+// round is column + diagonal, followed by every tuple in `extra_rounds`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChaChaSpec {
+        pub double_rounds: u32,
+        pub rotations: [u32; 4],
+        #[serde(default)]
+        pub extra_rounds: Vec<[usize; 4]>,
+        pub constant: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecError {
+        NoRounds,
+        TooManyRounds(u32),
+        BadRotation(u32),
+        BadIndex(usize),
+        RepeatedIndex([usize; 4]),
+        BadConstant(usize),
+        Json(String),
+}
+
+impl fmt::Display for SpecError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        SpecError::NoRounds => write!(f, "double_rounds must be at least 1"),
+                        SpecError::TooManyRounds(n) => write!(f, "double_rounds {} exceeds {}", n, MAX_DOUBLE_ROUNDS),
+                        SpecError::BadRotation(r) => write!(f, "rotation {} is outside 1..=31", r),
+                        SpecError::BadIndex(i) => write!(f, "state index {} is outside 0..16", i),
+                        SpecError::RepeatedIndex(t) => write!(f, "quarter-round {:?} repeats a state word", t),
+                        SpecError::BadConstant(n) => write!(f, "constant must be 16 printable ASCII bytes, got {} bytes", n),
+                        SpecError::Json(e) => write!(f, "invalid spec JSON: {}", e),
+                }
+        }
+}
+
+impl std::error::Error for SpecError {}
+
+impl From<ChaChaVariant> for ChaChaSpec {
+        fn from(variant: ChaChaVariant) -> Self {
+                let extra_rounds = match variant {
+                        ChaChaVariant::Standard => Vec::new(),
+                        ChaChaVariant::Custom => vec![[6, 3, 1, 0], [10, 7, 4, 2], [13, 11, 8, 5], [15, 14, 12, 9]],
+                };
+                ChaChaSpec {
+                        double_rounds: 10,
+                        rotations: [16, 12, 8, 7],
+                        extra_rounds,
+                        constant: "expand 32-byte k".to_string(),
+                }
+        }
+}
+
+impl ChaChaSpec {
+        pub fn from_json(json: &str) -> Result<Self, SpecError> {
+                let spec: ChaChaSpec = serde_json::from_str(json).map_err(|e| SpecError::Json(e.to_string()))?;
+                spec.validate()?;
+                Ok(spec)
+        }
+
+        pub fn to_json(&self) -> String {
+                serde_json::to_string_pretty(self).expect("spec serializes")
+        }
+
+        pub fn validate(&self) -> Result<(), SpecError> {
+                if self.double_rounds == 0 {
+                        return Err(SpecError::NoRounds);
+                }
+                if self.double_rounds > MAX_DOUBLE_ROUNDS {
+                        return Err(SpecError::TooManyRounds(self.double_rounds));
+                }
+                if let Some(&r) = self.rotations.iter().find(|&&r| r == 0 || r > 31) {
+                        return Err(SpecError::BadRotation(r));
+                }
+                for tuple in &self.extra_rounds {
+                        if let Some(&i) = tuple.iter().find(|&&i| i >= 16) {
+                                return Err(SpecError::BadIndex(i));
+                        }
+                        if tuple.iter().collect::<HashSet<_>>().len() != 4 {
+                                return Err(SpecError::RepeatedIndex(*tuple));
+                        }
+                }
+                if self.constant.len() != 16 || !self.constant.bytes().all(|b| b.is_ascii_graphic() || b == b' ') {
+                        return Err(SpecError::BadConstant(self.constant.len()));
+                }
+                Ok(())
+        }
+
+        fn quarter_round(&self, st: &mut [u32; 16], i: usize, j: usize, k: usize, l: usize) {
+                let [r1, r2, r3, r4] = self.rotations;
+                let (mut a, mut b, mut c, mut d) = (st[i], st[j], st[k], st[l]);
+                a = a.wrapping_add(b); d ^= a; d = d.rotate_left(r1);
+                c = c.wrapping_add(d); b ^= c; b = b.rotate_left(r2);
+                a = a.wrapping_add(b); d ^= a; d = d.rotate_left(r3);
+                c = c.wrapping_add(d); b ^= c; b = b.rotate_left(r4);
+                st[i] = a; st[j] = b; st[k] = c; st[l] = d;
+        }
+
+        // Expects a spec that passed `validate`.
+        pub fn block(&self, key: &[u8], counter: u32, nonce: &[u8]) -> Result<Vec<u8>, CipherError> {
+                check_key(key, 32)?;
+                check_nonce(nonce, 12)?;
+                let mut x = [0u32; 16];
+                x[0..4].copy_from_slice(&to_le_u32_words(self.constant.as_bytes())?);
+                x[4..12].copy_from_slice(&to_le_u32_words(key)?);
+                x[12] = counter;
+                x[13..16].copy_from_slice(&to_le_u32_words(nonce)?);
+                let mut st = x;
+                for _ in 0..self.double_rounds {
+                        for s in 0..4 {
+                                self.quarter_round(&mut st, s, s + 4, s + 8, s + 12);
+                        }
+                        for s in 0..4 {
+                                self.quarter_round(&mut st, s, ((s + 1) & 3) + 4, ((s + 2) & 3) + 8, ((s + 3) & 3) + 12);
+                        }
+                        for &[i, j, k, l] in &self.extra_rounds {
+                                self.quarter_round(&mut st, i, j, k, l);
+                        }
+                }
+                for i in 0..16 { st[i] = st[i].wrapping_add(x[i]); }
+                let out = words_to_le_bytes(&st);
+                x.zeroize(); st.zeroize();
+                Ok(out)
+        }
+
+        pub fn encrypt(&self, key: &[u8], nonce: &[u8], counter: u32, plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+                let mut ctr = counter;
+                let mut out = Vec::with_capacity(plaintext.len());
+                for chunk in plaintext.chunks(64) {
+                        let block = Zeroizing::new(self.block(key, ctr, nonce)?);
+                        out.extend(chunk.iter().zip(block.iter()).map(|(p, k)| p ^ k));
+                        ctr = ctr.wrapping_add(1);
+                }
+                Ok(out)
+        }
+
+        // Python reference solver in the shape of solve/custom_chacha20.py,
+        // with this spec's constants filled in.
+        pub fn python_solver(&self) -> String {
+                let [r1, r2, r3, r4] = self.rotations;
+                let extra: String = self
+                        .extra_rounds
+                        .iter()
+                        .map(|[i, j, k, l]| format!("    qr_idx(st, {}, {}, {}, {})\n", i, j, k, l))
+                        .collect();
+                PYTHON_TEMPLATE
+                        .replace("@CONST@", &format!("{:?}", self.constant))
+                        .replace("@R1@", &r1.to_string())
+                        .replace("@R2@", &r2.to_string())
+                        .replace("@R3@", &r3.to_string())
+                        .replace("@R4@", &r4.to_string())
+                        .replace("@ROUNDS@", &self.double_rounds.to_string())
+                        .replace("@EXTRA@", if extra.is_empty() { "    pass\n" } else { &extra })
+                        .replace("@SPEC@", &self.to_json().replace('\n', "\n# "))
+        }
+}
+
+const PYTHON_TEMPLATE: &str = r#"import struct
+import sys
+
+# Generated from ChaChaSpec:
+# @SPEC@
+
+CONST = @CONST@.encode("ascii")
+INITIAL_COUNTER = 1
+
+def rotl32(x, r):
+    x &= 0xFFFFFFFF
+    return ((x << r) & 0xFFFFFFFF) | (x >> (32 - r))
+
+def to_le_u32_words(b):
+    assert len(b) % 4 == 0
+    return [struct.unpack("<I", b[i:i+4])[0] for i in range(0, len(b), 4)]
+
+def words_to_le_bytes(words):
+    return b"".join(struct.pack("<I", w & 0xFFFFFFFF) for w in words)
+
+def qr_idx(st, i, j, k, l):
+    a, b, c, d = st[i], st[j], st[k], st[l]
+    a = (a + b) & 0xFFFFFFFF; d ^= a; d = rotl32(d, @R1@)
+    c = (c + d) & 0xFFFFFFFF; b ^= c; b = rotl32(b, @R2@)
+    a = (a + b) & 0xFFFFFFFF; d ^= a; d = rotl32(d, @R3@)
+    c = (c + d) & 0xFFFFFFFF; b ^= c; b = rotl32(b, @R4@)
+    st[i], st[j], st[k], st[l] = a, b, c, d
+
+def round_column(st):
+    for s in range(4):
+        qr_idx(st, s, s + 4, s + 8, s + 12)
+
+def round_diagonal(st):
+    for s in range(4):
+        qr_idx(st, s, ((s + 1) & 3) + 4, ((s + 2) & 3) + 8, ((s + 3) & 3) + 12)
+
+def round_extra(st):
+@EXTRA@
+def block(key, counter, nonce):
+    if len(key) != 32 or len(nonce) != 12:
+        raise ValueError("key must be 32 bytes and nonce 12 bytes")
+    x = to_le_u32_words(CONST) + to_le_u32_words(key) + [counter & 0xFFFFFFFF] + to_le_u32_words(nonce)
+    st = x.copy()
+    for _ in range(@ROUNDS@):
+        round_column(st)
+        round_diagonal(st)
+        round_extra(st)
+    return words_to_le_bytes([(s + v) & 0xFFFFFFFF for s, v in zip(st, x)])
+
+def decrypt(key, nonce, counter, ciphertext):
+    out = bytearray()
+    for off in range(0, len(ciphertext), 64):
+        ks = block(key, (counter + off // 64) & 0xFFFFFFFF, nonce)
+        out += bytes(c ^ k for c, k in zip(ciphertext[off:off + 64], ks))
+    return bytes(out)
+
+if __name__ == "__main__":
+    if len(sys.argv) != 4:
+        sys.exit("usage: solver.py <key> <nonce-hex> <ciphertext-hex>")
+    key = sys.argv[1].encode("utf-8")
+    nonce = bytes.fromhex(sys.argv[2])
+    plaintext = decrypt(key, nonce, INITIAL_COUNTER, bytes.fromhex(sys.argv[3]))
+    try:
+        print(plaintext.decode("utf-8"))
+    except UnicodeDecodeError:
+        print(plaintext.hex())
+"#;
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+        use crate::server::verify::chacha_logic::{chacha20_block, encrypt_variant};
+
+        // The hand-written variants are what the server actually runs, so the
+        // specs derived from them must produce the same block and multi-block
+        // stream.
+        #[test]
+        fn specs_match_the_hand_written_variants() {
+                let key: Vec<u8> = (0u8..32).collect();
+                let nonce: Vec<u8> = (0u8..12).map(|b| b.wrapping_mul(17)).collect();
+                let plaintext: Vec<u8> = (0u8..150).collect();
+                for variant in [ChaChaVariant::Standard, ChaChaVariant::Custom] {
+                        let spec = ChaChaSpec::from(variant);
+                        spec.validate().unwrap();
+                        assert_eq!(spec.block(&key, 7, &nonce).unwrap(), chacha20_block(variant, &key, 7, &nonce).unwrap());
+                        assert_eq!(
+                                spec.encrypt(&key, &nonce, u32::MAX, &plaintext).unwrap(),
+                                encrypt_variant(variant, &key, &nonce, u32::MAX, &plaintext).unwrap(),
+                                "{:?} stream across the counter wrap",
+                                variant,
+                        );
+                }
+        }
+}
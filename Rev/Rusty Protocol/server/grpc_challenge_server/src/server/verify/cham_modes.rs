@@ -0,0 +1,179 @@
+use super::cham_logic::{cham_decrypt_block, cham_encrypt_block, ChamVariant};
+use super::error::{check_iv, check_key, CipherError};
+use zeroize::Zeroizing;
+
+// Classic block modes over any ChamVariant at its `rounds()`. ECB and CBC
+// pad with PKCS#7; CFB (full-block feedback) and OFB are stream modes and
+// keep the plaintext length. The stage-4 CTR mode stays in cham_logic.
+
+fn encrypt_block(variant: ChamVariant, key: &[u8], block: &[u8]) -> Result<Vec<u8>, CipherError> {
+        cham_encrypt_block(variant, key, block, variant.rounds())
+}
+
+fn decrypt_block(variant: ChamVariant, key: &[u8], block: &[u8]) -> Result<Vec<u8>, CipherError> {
+        cham_decrypt_block(variant, key, block, variant.rounds())
+}
+
+fn pkcs7_pad(data: &[u8], bs: usize) -> Vec<u8> {
+        let n = bs - data.len() % bs;
+        let mut out = data.to_vec();
+        out.resize(data.len() + n, n as u8);
+        out
+}
+
+fn pkcs7_unpad(mut data: Vec<u8>, bs: usize) -> Result<Vec<u8>, CipherError> {
+        let n = *data.last().ok_or(CipherError::BadPadding)? as usize;
+        if n == 0 || n > bs || data[data.len() - n..].iter().any(|&b| b as usize != n) {
+                return Err(CipherError::BadPadding);
+        }
+        data.truncate(data.len() - n);
+        Ok(data)
+}
+
+fn check_whole_blocks(data: &[u8], bs: usize) -> Result<(), CipherError> {
+        if data.is_empty() || !data.len().is_multiple_of(bs) {
+                return Err(CipherError::PartialBlock { len: data.len(), block: bs });
+        }
+        Ok(())
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+        a.iter().zip(b).map(|(x, y)| x ^ y).collect()
+}
+
+pub fn ecb_encrypt(variant: ChamVariant, key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+        check_key(key, variant.key_len())?;
+        let bs = variant.block_len();
+        let mut out = Vec::with_capacity(plaintext.len() + bs);
+        for block in pkcs7_pad(plaintext, bs).chunks(bs) {
+                out.extend(encrypt_block(variant, key, block)?);
+        }
+        Ok(out)
+}
+
+pub fn ecb_decrypt(variant: ChamVariant, key: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, CipherError> {
+        check_key(key, variant.key_len())?;
+        let bs = variant.block_len();
+        check_whole_blocks(ciphertext, bs)?;
+        let mut out = Vec::with_capacity(ciphertext.len());
+        for block in ciphertext.chunks(bs) {
+                out.extend(decrypt_block(variant, key, block)?);
+        }
+        pkcs7_unpad(out, bs)
+}
+
+pub fn cbc_encrypt(variant: ChamVariant, key: &[u8], iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+        check_key(key, variant.key_len())?;
+        let bs = variant.block_len();
+        check_iv(iv, bs)?;
+        let mut prev = iv.to_vec();
+        let mut out = Vec::with_capacity(plaintext.len() + bs);
+        for block in pkcs7_pad(plaintext, bs).chunks(bs) {
+                prev = encrypt_block(variant, key, &xor(block, &prev))?;
+                out.extend_from_slice(&prev);
+        }
+        Ok(out)
+}
+
+pub fn cbc_decrypt(variant: ChamVariant, key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, CipherError> {
+        check_key(key, variant.key_len())?;
+        let bs = variant.block_len();
+        check_iv(iv, bs)?;
+        check_whole_blocks(ciphertext, bs)?;
+        let mut prev = iv;
+        let mut out = Vec::with_capacity(ciphertext.len());
+        for block in ciphertext.chunks(bs) {
+                out.extend(xor(&decrypt_block(variant, key, block)?, prev));
+                prev = block;
+        }
+        pkcs7_unpad(out, bs)
+}
+
+fn cfb(variant: ChamVariant, key: &[u8], iv: &[u8], input: &[u8], decrypting: bool) -> Result<Vec<u8>, CipherError> {
+        check_key(key, variant.key_len())?;
+        let bs = variant.block_len();
+        check_iv(iv, bs)?;
+        let mut feedback = iv.to_vec();
+        let mut out = Vec::with_capacity(input.len());
+        for chunk in input.chunks(bs) {
+                let ks = Zeroizing::new(encrypt_block(variant, key, &feedback)?);
+                let produced = xor(chunk, &ks);
+                feedback = if decrypting { chunk.to_vec() } else { produced.clone() };
+                out.extend(produced);
+        }
+        Ok(out)
+}
+
+pub fn cfb_encrypt(variant: ChamVariant, key: &[u8], iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+        cfb(variant, key, iv, plaintext, false)
+}
+
+pub fn cfb_decrypt(variant: ChamVariant, key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, CipherError> {
+        cfb(variant, key, iv, ciphertext, true)
+}
+
+// OFB is its own inverse.
+pub fn ofb_apply(variant: ChamVariant, key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>, CipherError> {
+        check_key(key, variant.key_len())?;
+        let bs = variant.block_len();
+        check_iv(iv, bs)?;
+        let mut stream = Zeroizing::new(iv.to_vec());
+        let mut out = Vec::with_capacity(data.len());
+        for chunk in data.chunks(bs) {
+                let next = Zeroizing::new(encrypt_block(variant, key, &stream)?);
+                stream.copy_from_slice(&next);
+                out.extend(xor(chunk, &stream));
+        }
+        Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        const VARIANTS: [ChamVariant; 4] = [
+                ChamVariant::Cham64_128,
+                ChamVariant::Cham128_128,
+                ChamVariant::Cham128_256,
+                ChamVariant::Challenge,
+        ];
+
+        fn inputs(variant: ChamVariant) -> (Vec<u8>, Vec<u8>) {
+                let key = (0u8..variant.key_len() as u8).collect();
+                let iv = (0..variant.block_len() as u8).map(|b| b.wrapping_mul(29)).collect();
+                (key, iv)
+        }
+
+        // Every mode over every variant at lengths around the block boundaries.
+        #[test]
+        fn modes_round_trip() {
+                for variant in VARIANTS {
+                        let (key, iv) = inputs(variant);
+                        for len in [0, 1, variant.block_len() - 1, variant.block_len(), 37] {
+                                let pt: Vec<u8> = (0..len as u8).collect();
+                                let ct = ecb_encrypt(variant, &key, &pt).unwrap();
+                                assert_eq!(ecb_decrypt(variant, &key, &ct).unwrap(), pt, "{:?} ECB, {} bytes", variant, len);
+                                let ct = cbc_encrypt(variant, &key, &iv, &pt).unwrap();
+                                assert_eq!(cbc_decrypt(variant, &key, &iv, &ct).unwrap(), pt, "{:?} CBC, {} bytes", variant, len);
+                                let ct = cfb_encrypt(variant, &key, &iv, &pt).unwrap();
+                                assert_eq!(ct.len(), len);
+                                assert_eq!(cfb_decrypt(variant, &key, &iv, &ct).unwrap(), pt, "{:?} CFB, {} bytes", variant, len);
+                                let ct = ofb_apply(variant, &key, &iv, &pt).unwrap();
+                                assert_eq!(ct.len(), len);
+                                assert_eq!(ofb_apply(variant, &key, &iv, &ct).unwrap(), pt, "{:?} OFB, {} bytes", variant, len);
+                        }
+                }
+        }
+
+        // Corrupted padding is reported, not returned.
+        #[test]
+        fn cbc_rejects_corrupted_padding() {
+                for variant in VARIANTS {
+                        let (key, iv) = inputs(variant);
+                        let mut ct = cbc_encrypt(variant, &key, &iv, b"corrupted padding").unwrap();
+                        let last = ct.len() - variant.block_len() - 1;
+                        ct[last] ^= 0x80;
+                        assert_eq!(cbc_decrypt(variant, &key, &iv, &ct), Err(CipherError::BadPadding));
+                }
+        }
+}
@@ -0,0 +1,53 @@
+use std::fmt;
+
+// Bad-input failures from the cipher modules. The release profile aborts on
+// panic, so a wrong length reaching these functions has to come back as a
+// value the caller can turn into a player-facing failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherError {
+        KeyLength { expected: usize, got: usize },
+        NonceLength { expected: usize, got: usize },
+        IvLength { expected: usize, got: usize },
+        BlockLength { expected: usize, got: usize },
+        Unaligned { len: usize },
+        PartialBlock { len: usize, block: usize },
+        BadPadding,
+        CounterWidth { width: usize },
+        CounterOverflow,
+        TagMismatch,
+}
+
+impl fmt::Display for CipherError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                        CipherError::KeyLength { expected, got } => write!(f, "key must be {} bytes, got {}", expected, got),
+                        CipherError::NonceLength { expected, got } => write!(f, "nonce must be {} bytes, got {}", expected, got),
+                        CipherError::IvLength { expected, got } => write!(f, "IV must be {} bytes, got {}", expected, got),
+                        CipherError::BlockLength { expected, got } => write!(f, "block must be {} bytes, got {}", expected, got),
+                        CipherError::Unaligned { len } => write!(f, "{} bytes is not a whole number of 32-bit words", len),
+                        CipherError::PartialBlock { len, block } => write!(f, "{} bytes is not a whole number of {}-byte blocks", len, block),
+                        CipherError::BadPadding => write!(f, "invalid PKCS#7 padding"),
+                        CipherError::CounterWidth { width } => write!(f, "counter width {} is outside 1..=16 bytes", width),
+                        CipherError::CounterOverflow => write!(f, "CTR counter would overflow"),
+                        CipherError::TagMismatch => write!(f, "authentication tag mismatch"),
+                }
+        }
+}
+
+impl std::error::Error for CipherError {}
+
+pub(crate) fn check_key(key: &[u8], expected: usize) -> Result<(), CipherError> {
+        if key.len() == expected { Ok(()) } else { Err(CipherError::KeyLength { expected, got: key.len() }) }
+}
+
+pub(crate) fn check_nonce(nonce: &[u8], expected: usize) -> Result<(), CipherError> {
+        if nonce.len() == expected { Ok(()) } else { Err(CipherError::NonceLength { expected, got: nonce.len() }) }
+}
+
+pub(crate) fn check_iv(iv: &[u8], expected: usize) -> Result<(), CipherError> {
+        if iv.len() == expected { Ok(()) } else { Err(CipherError::IvLength { expected, got: iv.len() }) }
+}
+
+pub(crate) fn check_block(block: &[u8], expected: usize) -> Result<(), CipherError> {
+        if block.len() == expected { Ok(()) } else { Err(CipherError::BlockLength { expected, got: block.len() }) }
+}
@@ -0,0 +1,200 @@
+use std::convert::TryInto;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use super::block_ctr::{self, Block16};
+use super::error::{check_block, check_key, CipherError};
+
+// LEA (TTAS.KO-12.0223). `Challenge` is LEA-256 with every key-schedule
+// delta rotated by 16 bits; `deltas` is the hook for further tweaks.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeaVariant {
+        Lea128 = 0,
+        Lea192 = 1,
+        Lea256 = 2,
+        Challenge = 3,
+}
+
+const DELTA: [u32; 8] = [
+        0xc3efe9db, 0x44626b02, 0x79e27c8a, 0x78df30ec,
+        0x715ea49e, 0xc785da0a, 0xe04ef22a, 0xe5c40957,
+];
+
+impl LeaVariant {
+        pub fn key_len(self) -> usize {
+                match self {
+                        LeaVariant::Lea128 => 16,
+                        LeaVariant::Lea192 => 24,
+                        LeaVariant::Lea256 | LeaVariant::Challenge => 32,
+                }
+        }
+
+        pub fn rounds(self) -> usize {
+                match self {
+                        LeaVariant::Lea128 => 24,
+                        LeaVariant::Lea192 => 28,
+                        LeaVariant::Lea256 | LeaVariant::Challenge => 32,
+                }
+        }
+
+        pub fn deltas(self) -> [u32; 8] {
+                match self {
+                        LeaVariant::Challenge => DELTA.map(|d| d.rotate_left(16)),
+                        _ => DELTA,
+                }
+        }
+}
+
+// Round keys expanded once, wiped on drop; each round uses six 32-bit words.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct LeaKey {
+        rk: [[u32; 6]; 32],
+        rounds: usize,
+}
+
+impl LeaKey {
+        pub fn new(variant: LeaVariant, key: &[u8]) -> Result<Self, CipherError> {
+                check_key(key, variant.key_len())?;
+                let delta = variant.deltas();
+                let mut t: Zeroizing<Vec<u32>> = Zeroizing::new(key.chunks_exact(4).map(|c| u32::from_le_bytes(c.try_into().unwrap())).collect());
+                let mut rk = [[0u32; 6]; 32];
+                let rounds = variant.rounds();
+                for (i, out) in rk.iter_mut().enumerate().take(rounds) {
+                        let d = delta[i % t.len()];
+                        let step = |t: &mut u32, j: usize, r: u32| {
+                                *t = t.wrapping_add(d.rotate_left((i + j) as u32)).rotate_left(r);
+                        };
+                        match t.len() {
+                                4 => {
+                                        for (j, r) in [1, 3, 6, 11].into_iter().enumerate() {
+                                                step(&mut t[j], j, r);
+                                        }
+                                        *out = [t[0], t[1], t[2], t[1], t[3], t[1]];
+                                }
+                                6 => {
+                                        for (j, r) in [1, 3, 6, 11, 13, 17].into_iter().enumerate() {
+                                                step(&mut t[j], j, r);
+                                        }
+                                        out.copy_from_slice(&t);
+                                }
+                                _ => {
+                                        for (j, r) in [1, 3, 6, 11, 13, 17].into_iter().enumerate() {
+                                                step(&mut t[(6 * i + j) % 8], j, r);
+                                        }
+                                        for (j, o) in out.iter_mut().enumerate() {
+                                                *o = t[(6 * i + j) % 8];
+                                        }
+                                }
+                        }
+                }
+                Ok(LeaKey { rk, rounds })
+        }
+
+        pub fn encrypt_block(&self, block: &[u8; 16]) -> [u8; 16] {
+                let mut x = load(block);
+                for k in &self.rk[..self.rounds] {
+                        x = [
+                                (x[0] ^ k[0]).wrapping_add(x[1] ^ k[1]).rotate_left(9),
+                                (x[1] ^ k[2]).wrapping_add(x[2] ^ k[3]).rotate_right(5),
+                                (x[2] ^ k[4]).wrapping_add(x[3] ^ k[5]).rotate_right(3),
+                                x[0],
+                        ];
+                }
+                store(&x)
+        }
+
+        pub fn decrypt_block(&self, block: &[u8; 16]) -> [u8; 16] {
+                let mut x = load(block);
+                for k in self.rk[..self.rounds].iter().rev() {
+                        let x0 = x[3];
+                        let x1 = x[0].rotate_right(9).wrapping_sub(x0 ^ k[0]) ^ k[1];
+                        let x2 = x[1].rotate_left(5).wrapping_sub(x1 ^ k[2]) ^ k[3];
+                        let x3 = x[2].rotate_left(3).wrapping_sub(x2 ^ k[4]) ^ k[5];
+                        x = [x0, x1, x2, x3];
+                }
+                store(&x)
+        }
+}
+
+impl Block16 for LeaKey {
+        fn encrypt_block(&self, block: &[u8; 16]) -> [u8; 16] {
+                LeaKey::encrypt_block(self, block)
+        }
+}
+
+fn load(block: &[u8; 16]) -> [u32; 4] {
+        let w = |j: usize| u32::from_le_bytes(block[4 * j..4 * j + 4].try_into().unwrap());
+        [w(0), w(1), w(2), w(3)]
+}
+
+fn store(x: &[u32; 4]) -> [u8; 16] {
+        let mut out = [0u8; 16];
+        for (dst, w) in out.chunks_exact_mut(4).zip(x) {
+                dst.copy_from_slice(&w.to_le_bytes());
+        }
+        out
+}
+
+#[no_mangle] #[inline(never)]
+pub fn lea_encrypt_block(variant: LeaVariant, key: &[u8], block: &[u8]) -> Result<[u8; 16], CipherError> {
+        check_block(block, 16)?;
+        Ok(LeaKey::new(variant, key)?.encrypt_block(block.try_into().unwrap()))
+}
+
+#[no_mangle] #[inline(never)]
+pub fn lea_decrypt_block(variant: LeaVariant, key: &[u8], block: &[u8]) -> Result<[u8; 16], CipherError> {
+        check_block(block, 16)?;
+        Ok(LeaKey::new(variant, key)?.decrypt_block(block.try_into().unwrap()))
+}
+
+#[no_mangle] #[inline(never)]
+pub fn lea_encrypt_ctr(variant: LeaVariant, key: &[u8], prefix: &[u8], start: u128, plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+        block_ctr::encrypt_ctr(LeaKey::new(variant, key)?, prefix, start, plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+        use super::*;
+
+        // The standard vectors are the TTAS.KO-12.0223 ones (key 0f 1e 2d .. and
+        // plaintext 10 11 .., 20 21 .., 30 31 ..). The challenge vector uses key
+        // 00 01 .. and plaintext 00 11 22 ..; it is a regression value from this
+        // code, so a change to the tweak shows up here.
+        const KAT_KEY: &str = "0f1e2d3c4b5a69788796a5b4c3d2e1f0f0e1d2c3b4a5968778695a4b3c2d1e0f";
+        const KATS: &[(LeaVariant, &str, &str)] = &[
+                (LeaVariant::Lea128, "101112131415161718191a1b1c1d1e1f", "9fc84e3528c6c6185532c7a704648bfd"),
+                (LeaVariant::Lea192, "202122232425262728292a2b2c2d2e2f", "6fb95e325aad1b878cdcf5357674c6f2"),
+                (LeaVariant::Lea256, "303132333435363738393a3b3c3d3e3f", "d651aff647b189c13a8900ca27f9e197"),
+        ];
+        const KAT_CHALLENGE_PLAINTEXT: &str = "00112233445566778899aabbccddeeff";
+        const KAT_CHALLENGE_CIPHERTEXT: &str = "c9fee90fa0eb4d34577be3caec8adf62";
+
+        fn challenge_key() -> Vec<u8> {
+                (0u8..32).collect()
+        }
+
+        fn assert_block(variant: LeaVariant, key: &[u8], pt: &str, expected: &str) {
+                let pt = hex::decode(pt).unwrap();
+                let out = lea_encrypt_block(variant, key, &pt).unwrap();
+                assert_eq!(hex::encode(out), expected, "{:?}", variant);
+                assert_eq!(lea_decrypt_block(variant, key, &out).unwrap(), pt[..]);
+        }
+
+        #[test]
+        fn standard_blocks_match_tta_vectors() {
+                let key = hex::decode(KAT_KEY).unwrap();
+                for &(variant, pt, expected) in KATS {
+                        assert_block(variant, &key[..variant.key_len()], pt, expected);
+                }
+        }
+
+        #[test]
+        fn challenge_block_matches_pinned_vector() {
+                assert_block(LeaVariant::Challenge, &challenge_key(), KAT_CHALLENGE_PLAINTEXT, KAT_CHALLENGE_CIPHERTEXT);
+        }
+
+        #[test]
+        fn ctr_passes_the_shared_checks() {
+                block_ctr::tests::check_ctr(&LeaKey::new(LeaVariant::Challenge, &challenge_key()).unwrap());
+        }
+}
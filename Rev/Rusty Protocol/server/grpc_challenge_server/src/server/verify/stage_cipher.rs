@@ -0,0 +1,62 @@
+use subtle::ConstantTimeEq;
+
+use super::error::CipherError;
+
+// A cipher-based stage as the verification code sees it: fixed key and
+// nonce sizes, whole-message encrypt/decrypt with the stage's counter
+// layout baked in, and a description for logs.
+pub trait StageCipher {
+        fn key_len(&self) -> usize;
+        fn nonce_len(&self) -> usize;
+        fn encrypt(&self, key: &[u8], nonce: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, CipherError>;
+        fn decrypt(&self, key: &[u8], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, CipherError>;
+        fn description(&self) -> &'static str;
+}
+
+// Session nonces travel as hex; anything but exactly nonce_len() bytes is
+// rejected.
+pub fn decode_nonce<C: StageCipher + ?Sized>(cipher: &C, nonce_hex: &str) -> Option<Vec<u8>> {
+        hex::decode(nonce_hex).ok().filter(|n| n.len() == cipher.nonce_len())
+}
+
+// Re-encrypts the player's answer under the nonce their session was issued
+// and compares it, in constant time, with the ciphertext stored for that
+// session. Returns the match and the player's ciphertext hex, or an error
+// tag in its place.
+pub fn verify_stage_input<C: StageCipher + ?Sized>(
+        cipher: &C, key: &[u8], nonce_hex: &str, expected_hex: Option<String>, user_input: &str,
+) -> (bool, String) {
+        let expected_hex = match expected_hex {
+                Some(c) => c, None => return (false, "SERVER_ERROR".to_string()),
+        };
+        let nonce = match decode_nonce(cipher, nonce_hex) {
+                Some(n) => n, None => return (false, "NONCE_ERROR".to_string()),
+        };
+        let user_cipher_hex = match cipher.encrypt(key, &nonce, user_input.as_bytes()) {
+                Ok(bytes) => hex::encode(bytes),
+                Err(e) => {
+                        println!("[!] {} rejected a player answer: {}", cipher.description(), e);
+                        return (false, "ENCRYPT_ERROR".to_string());
+                }
+        };
+        let is_match = user_cipher_hex.as_bytes().ct_eq(expected_hex.as_bytes()).into();
+        (is_match, user_cipher_hex)
+}
+
+// Startup guard for a deployed stage: the configured key has the size the
+// cipher takes, and decrypt inverts encrypt under it.
+pub fn check_stage<C: StageCipher + ?Sized>(cipher: &C, key: &[u8]) -> Result<(), String> {
+        if key.len() != cipher.key_len() {
+                return Err(format!("{}: configured key is {} bytes, expected {}", cipher.description(), key.len(), cipher.key_len()));
+        }
+        let nonce: Vec<u8> = (0..cipher.nonce_len() as u8).collect();
+        let plaintext: Vec<u8> = (0u8..100).collect();
+        let round_trip = cipher
+                .encrypt(key, &nonce, &plaintext)
+                .and_then(|ct| cipher.decrypt(key, &nonce, &ct))
+                .map_err(|e| format!("{}: {}", cipher.description(), e))?;
+        if round_trip != plaintext {
+                return Err(format!("{}: decrypt does not invert encrypt", cipher.description()));
+        }
+        Ok(())
+}